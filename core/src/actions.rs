@@ -0,0 +1,201 @@
+//! named actions that can be bound to keys, mouse buttons and gamepad inputs
+//!
+//! Bindings are written as strings so they can be saved and loaded by the game:
+//! - `"Space"` or `"key:Space"` for a key, using the names from `KEY_LIST`
+//! - `"mouse:Left"`, `"mouse:Middle"` or `"mouse:Right"` for a mouse button
+//! - `"pad:A"` for a button on any gamepad, or `"pad1:A"` for the gamepad with index 1
+//! - `"pad:LeftStickX"` for an analog axis, which also counts as held when pushed past halfway
+
+use crate::prelude::*;
+use crate::{gamepad, ButtonState, State};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Pad {
+    Any,
+    Index(usize),
+}
+
+#[derive(Clone, Debug)]
+pub enum Binding {
+    Key(Key),
+    Mouse(usize),
+    PadButton(Pad, GamepadButton),
+    PadAxis(Pad, GamepadAxis),
+}
+
+const MOUSE_BUTTONS: [&str; 3] = ["Left", "Middle", "Right"];
+
+/// How far an axis has to be pushed for it to count as held
+const AXIS_THRESHOLD: f32 = 0.5;
+
+/// The index of a mouse button in `State::mouse` from its name
+pub fn parse_mouse_button(name: &str) -> Option<usize> {
+    MOUSE_BUTTONS.iter().position(|b| *b == name)
+}
+
+impl std::str::FromStr for Binding {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (device, input) = match s.find(':') {
+            Some(i) => (&s[..i], &s[i + 1..]),
+            None => ("key", s),
+        };
+        let binding = match device {
+            "key" => crate::pyqs::parse_key(input).map(Binding::Key),
+            "mouse" => parse_mouse_button(input).map(Binding::Mouse),
+            _ if device.starts_with("pad") => {
+                let pad = match &device[3..] {
+                    "" => Pad::Any,
+                    idx => Pad::Index(
+                        idx.parse()
+                            .map_err(|_| format!("invalid gamepad index in {:?}", s))?,
+                    ),
+                };
+                gamepad::parse_button(input)
+                    .map(|b| Binding::PadButton(pad, b))
+                    .or_else(|| gamepad::parse_axis(input).map(|a| Binding::PadAxis(pad, a)))
+            }
+            _ => return Err(format!("unknown input device in binding {:?}", s)),
+        };
+        binding.ok_or_else(|| format!("unknown input in binding {:?}", s))
+    }
+}
+
+impl std::fmt::Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let pad = |f: &mut std::fmt::Formatter, pad: &Pad| match pad {
+            Pad::Any => write!(f, "pad:"),
+            Pad::Index(i) => write!(f, "pad{}:", i),
+        };
+        match self {
+            Binding::Key(k) => write!(f, "{:?}", k),
+            Binding::Mouse(i) => write!(f, "mouse:{}", MOUSE_BUTTONS[*i]),
+            Binding::PadButton(p, b) => {
+                pad(f, p)?;
+                write!(f, "{:?}", b)
+            }
+            Binding::PadAxis(p, a) => {
+                pad(f, p)?;
+                write!(f, "{:?}", a)
+            }
+        }
+    }
+}
+
+/// Combine the states of several inputs that all trigger the same thing, so that e.g. the
+/// action is only "pressed" if nothing else was already holding it down
+fn combine(states: impl Iterator<Item = ButtonState>) -> ButtonState {
+    let rank = |s: &ButtonState| match s {
+        ButtonState::Held => 3,
+        ButtonState::Pressed => 2,
+        ButtonState::Released => 1,
+        ButtonState::NotPressed => 0,
+    };
+    states.max_by_key(rank).unwrap_or(ButtonState::NotPressed)
+}
+
+fn axis_state(value: f32) -> ButtonState {
+    if value.abs() > AXIS_THRESHOLD {
+        ButtonState::Held
+    } else {
+        ButtonState::NotPressed
+    }
+}
+
+impl Binding {
+    fn pads(pad: Pad, state: &State) -> impl Iterator<Item = &gamepad::Gamepad> {
+        state
+            .gamepads
+            .iter()
+            .enumerate()
+            .filter(move |(i, p)| p.connected && (pad == Pad::Any || pad == Pad::Index(*i)))
+            .map(|(_, p)| p)
+    }
+
+    pub fn state(&self, state: &State) -> ButtonState {
+        match self {
+            Binding::Key(k) => state.keyboard[*k as usize],
+            Binding::Mouse(i) => state.mouse[*i],
+            Binding::PadButton(pad, b) => combine(Self::pads(*pad, state).map(|p| p.button(*b))),
+            Binding::PadAxis(..) => axis_state(self.value(state)),
+        }
+    }
+
+    pub fn is_analog(&self) -> bool {
+        matches!(self, Binding::PadAxis(..))
+    }
+
+    /// The analog value of the input from -1 to 1, where buttons are either 0 or 1
+    pub fn value(&self, state: &State) -> f32 {
+        match self {
+            Binding::PadAxis(pad, a) => Self::pads(*pad, state)
+                .map(|p| p.axis(*a))
+                .max_by(|x, y| x.abs().partial_cmp(&y.abs()).unwrap())
+                .unwrap_or(0.0),
+            _ => {
+                if self.state(state).is_down() {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// The combined state of everything bound to an action
+pub fn action_state(bindings: &[Binding], state: &State) -> ButtonState {
+    combine(bindings.iter().map(|b| b.state(state)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Binding {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_each_device() {
+        assert!(matches!(parse("Space"), Binding::Key(Key::Space)));
+        assert!(matches!(parse("key:Space"), Binding::Key(Key::Space)));
+        assert!(matches!(parse("mouse:Right"), Binding::Mouse(2)));
+        assert!(matches!(
+            parse("pad:A"),
+            Binding::PadButton(Pad::Any, GamepadButton::South)
+        ));
+        assert!(matches!(
+            parse("pad1:North"),
+            Binding::PadButton(Pad::Index(1), GamepadButton::North)
+        ));
+        assert!(matches!(
+            parse("pad:LeftStickX"),
+            Binding::PadAxis(Pad::Any, GamepadAxis::LeftStickX)
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_bindings() {
+        for s in &["", "Nope", "mouse:Side", "pad:Nope", "padx:A", "joystick:A"] {
+            assert!(s.parse::<Binding>().is_err(), "{:?} parsed", s);
+        }
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for s in &[
+            "Space",
+            "Escape",
+            "mouse:Left",
+            "pad:South",
+            "pad2:RightTrigger",
+            "pad:LeftStickY",
+        ] {
+            assert_eq!(parse(s).to_string(), *s);
+        }
+        // aliases and the explicit "key:" prefix come back in their canonical form
+        assert_eq!(parse("key:Space").to_string(), "Space");
+        assert_eq!(parse("pad0:Y").to_string(), "pad0:North");
+    }
+}
@@ -1,3 +1,4 @@
+use crate::atlas::Region;
 use crate::prelude::*;
 
 pub struct Animation {
@@ -41,12 +42,28 @@ impl Animation {
         frame % self.nframes
     }
 
-    pub fn draw(&self, gfx: &mut Graphics, location: Rectangle) {
+    /// Draw the current frame, or the `part` of it measured from its top left corner
+    pub fn draw(
+        &self,
+        gfx: &mut Graphics,
+        part: Option<Rectangle>,
+        location: Rectangle,
+        tint: Color,
+    ) {
         let n = self.nth();
 
-        let region = Rectangle::new(self.frame_size.x_comp() * n as f32, self.frame_size);
+        let frame = Region {
+            src: Rectangle::new(self.frame_size.x_comp() * n as f32, self.frame_size),
+            dest: Rectangle::new_sized(self.frame_size),
+            size: self.frame_size,
+        };
+        // parts are cut to the frame, so the next frame over doesn't show
+        let region = match part {
+            Some(part) => frame.part(part),
+            None => frame,
+        };
 
-        gfx.draw_subimage(&self.image, region, location);
+        gfx.draw_subimage_tinted(&self.image, region.src, region.place(location), tint);
     }
 
     #[allow(unused)]
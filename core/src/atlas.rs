@@ -0,0 +1,317 @@
+use crate::prelude::*;
+use anyhow::{bail, Context};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// A part of an image that can be drawn like a sprite
+#[derive(Clone, Copy, Debug)]
+pub struct Region {
+    /// Where the region is in the image
+    pub src: Rectangle,
+    /// Where the region goes in the untrimmed sprite, which is usually `src` moved to 0, 0
+    pub dest: Rectangle,
+    /// The size of the untrimmed sprite
+    pub size: Vector,
+}
+
+impl Region {
+    /// The whole of an image
+    pub fn whole(image: &Image) -> Region {
+        let size = image.size();
+        Region {
+            src: Rectangle::new_sized(size),
+            dest: Rectangle::new_sized(size),
+            size,
+        }
+    }
+
+    /// The `part` of the untrimmed sprite, measured from its top left corner
+    ///
+    /// Only the trimmed frame is read from, so neighbouring sprites in the image never show up;
+    /// anything in `part` outside of it is left empty.
+    pub fn part(&self, part: Rectangle) -> Region {
+        let (lo, hi) = (self.dest.pos, self.dest.pos + self.dest.size);
+        let end = part.pos + part.size;
+        let tl = Vector::new(part.pos.x.max(lo.x), part.pos.y.max(lo.y));
+        let br = Vector::new(end.x.min(hi.x), end.y.min(hi.y));
+        let size = Vector::new((br.x - tl.x).max(0.0), (br.y - tl.y).max(0.0));
+        let offset = self.src.pos - self.dest.pos;
+        Region {
+            src: Rectangle::new(offset + tl, size),
+            dest: Rectangle::new(tl - part.pos, size),
+            size: part.size,
+        }
+    }
+
+    /// Where to draw the region so the untrimmed sprite fills `location`
+    pub fn place(&self, location: Rectangle) -> Rectangle {
+        let (sx, sy) = (location.size.x / self.size.x, location.size.y / self.size.y);
+        Rectangle::new(
+            location.pos + Vector::new(self.dest.pos.x * sx, self.dest.pos.y * sy),
+            Vector::new(self.dest.size.x * sx, self.dest.size.y * sy),
+        )
+    }
+}
+
+/// Many sprites packed into one image
+pub struct Atlas {
+    pub image: Image,
+    pub regions: HashMap<String, Region>,
+}
+
+impl Atlas {
+    pub async fn load(gfx: &Graphics, image: String, data: String) -> anyhow::Result<Atlas> {
+        let bytes = load_file(&data)
+            .await
+            .with_context(|| format!("couldn't load atlas data {:?}", data))?;
+        let file: AtlasFile = serde_json::from_slice(&bytes)
+            .with_context(|| format!("{:?} isn't a TexturePacker or grid atlas", data))?;
+        let image = Image::load(gfx, image).await?;
+        let regions = file
+            .regions(image.size())
+            .with_context(|| format!("bad atlas data in {:?}", data))?;
+        Ok(Atlas { image, regions })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AtlasFile {
+    TexturePacker { frames: Frames },
+    Grid(Grid),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Frames {
+    /// TexturePacker's "JSON (Hash)" format
+    Hash(HashMap<String, Frame>),
+    /// TexturePacker's "JSON (Array)" format
+    Array(Vec<NamedFrame>),
+}
+
+#[derive(Deserialize)]
+struct NamedFrame {
+    filename: String,
+    #[serde(flatten)]
+    frame: Frame,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Frame {
+    frame: Rect,
+    #[serde(default)]
+    rotated: bool,
+    /// Where the trimmed frame goes in the original sprite
+    sprite_source_size: Option<Rect>,
+    source_size: Option<Size>,
+}
+
+#[derive(Deserialize)]
+struct Rect {
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+}
+
+#[derive(Deserialize)]
+struct Size {
+    w: f32,
+    h: f32,
+}
+
+/// The built-in format, for images split into equally sized cells, counting from the top left.
+///
+/// ```json
+/// { "cell": [16, 16], "margin": 0, "spacing": 0, "names": ["grass", "water", "sand"] }
+/// ```
+///
+/// Cells without a name are named by their index, starting from "0".
+#[derive(Deserialize)]
+struct Grid {
+    cell: (u32, u32),
+    /// Space around the edge of the image
+    #[serde(default)]
+    margin: u32,
+    /// Space between cells
+    #[serde(default)]
+    spacing: u32,
+    #[serde(default)]
+    names: Vec<String>,
+}
+
+impl AtlasFile {
+    fn regions(self, image_size: Vector) -> anyhow::Result<HashMap<String, Region>> {
+        match self {
+            AtlasFile::TexturePacker { frames } => {
+                let frames: Vec<(String, Frame)> = match frames {
+                    Frames::Hash(frames) => frames.into_iter().collect(),
+                    Frames::Array(frames) => frames
+                        .into_iter()
+                        .map(|NamedFrame { filename, frame }| (filename, frame))
+                        .collect(),
+                };
+                let mut regions = HashMap::new();
+                for (name, frame) in frames {
+                    let region = frame.region(&name)?;
+                    // "hero.png" can also be drawn as "hero"
+                    if let Some(dot) = name.rfind('.') {
+                        regions.entry(name[..dot].to_owned()).or_insert(region);
+                    }
+                    regions.insert(name, region);
+                }
+                Ok(regions)
+            }
+            AtlasFile::Grid(grid) => grid.regions(image_size),
+        }
+    }
+}
+
+impl Frame {
+    fn region(&self, name: &str) -> anyhow::Result<Region> {
+        if self.rotated {
+            bail!(
+                "frame {:?} is rotated, which isn't supported; turn off rotation when packing",
+                name
+            );
+        }
+        let Rect { x, y, w, h } = self.frame;
+        let src = Rectangle::new(Vector::new(x, y), Vector::new(w, h));
+        let dest = match &self.sprite_source_size {
+            Some(Rect { x, y, .. }) => Rectangle::new(Vector::new(*x, *y), Vector::new(w, h)),
+            None => Rectangle::new_sized(Vector::new(w, h)),
+        };
+        let size = match &self.source_size {
+            Some(Size { w, h }) => Vector::new(*w, *h),
+            None => src.size,
+        };
+        Ok(Region { src, dest, size })
+    }
+}
+
+impl Grid {
+    fn regions(self, image_size: Vector) -> anyhow::Result<HashMap<String, Region>> {
+        let (w, h) = self.cell;
+        if w == 0 || h == 0 {
+            bail!("grid cells must be bigger than 0x0");
+        }
+        // in u64 so that no margin or spacing can overflow
+        let (margin, spacing) = (u64::from(self.margin), u64::from(self.spacing));
+        let count = |image: f32, cell: u32| {
+            let space = (image as u64).saturating_sub(2 * margin) + spacing;
+            space / (u64::from(cell) + spacing)
+        };
+        let columns = count(image_size.x, w);
+        let rows = count(image_size.y, h);
+        if self.names.len() as u64 > columns * rows {
+            bail!(
+                "{} names were given, but the image only has {} cells",
+                self.names.len(),
+                columns * rows
+            );
+        }
+        let size = Vector::new(w as f32, h as f32);
+        let mut names = self.names.into_iter();
+        let mut regions = HashMap::new();
+        for i in 0..columns * rows {
+            let (col, row) = (i % columns, i / columns);
+            let pos = Vector::new(
+                (margin + col * (u64::from(w) + spacing)) as f32,
+                (margin + row * (u64::from(h) + spacing)) as f32,
+            );
+            let region = Region {
+                src: Rectangle::new(pos, size),
+                dest: Rectangle::new_sized(size),
+                size,
+            };
+            regions.insert(names.next().unwrap_or_else(|| i.to_string()), region);
+        }
+        Ok(regions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: f32, y: f32, w: f32, h: f32) -> Rectangle {
+        Rectangle::new(Vector::new(x, y), Vector::new(w, h))
+    }
+
+    /// An 8x8 frame at 10, 20 in the image, trimmed from a 12x12 sprite where it started at 2, 3
+    fn trimmed() -> Region {
+        Region {
+            src: rect(10.0, 20.0, 8.0, 8.0),
+            dest: rect(2.0, 3.0, 8.0, 8.0),
+            size: Vector::new(12.0, 12.0),
+        }
+    }
+
+    fn grid(json: &str, width: f32, height: f32) -> anyhow::Result<HashMap<String, Region>> {
+        let grid: Grid = serde_json::from_str(json).unwrap();
+        grid.regions(Vector::new(width, height))
+    }
+
+    #[test]
+    fn part_only_reads_the_trimmed_frame() {
+        let part = trimmed().part(rect(0.0, 0.0, 6.0, 6.0));
+        assert_eq!(part.src, rect(10.0, 20.0, 4.0, 3.0));
+        assert_eq!(part.dest, rect(2.0, 3.0, 4.0, 3.0));
+        assert_eq!(part.size, Vector::new(6.0, 6.0));
+
+        let part = trimmed().part(rect(4.0, 4.0, 8.0, 8.0));
+        assert_eq!(part.src, rect(12.0, 21.0, 6.0, 7.0));
+        assert_eq!(part.dest, rect(0.0, 0.0, 6.0, 7.0));
+    }
+
+    #[test]
+    fn part_outside_the_frame_is_empty() {
+        let part = trimmed().part(rect(0.0, 0.0, 2.0, 2.0));
+        assert_eq!(part.src.size, Vector::ZERO);
+        assert_eq!(part.size, Vector::new(2.0, 2.0));
+    }
+
+    #[test]
+    fn place_scales_the_trimmed_frame() {
+        let placed = trimmed().place(rect(100.0, 100.0, 24.0, 36.0));
+        assert_eq!(placed, rect(104.0, 109.0, 16.0, 24.0));
+    }
+
+    #[test]
+    fn grid_cells() {
+        let regions = grid(
+            r#"{ "cell": [16, 8], "margin": 1, "spacing": 2, "names": ["a", "b"] }"#,
+            54.0,
+            30.0,
+        )
+        .unwrap();
+        // 3 columns of 16 + 2 spacing fit in 54 - 2, and 3 rows of 8 in 30 - 2
+        assert_eq!(regions.len(), 9);
+        assert_eq!(regions["a"].src, rect(1.0, 1.0, 16.0, 8.0));
+        assert_eq!(regions["b"].src, rect(19.0, 1.0, 16.0, 8.0));
+        assert_eq!(regions["2"].src, rect(37.0, 1.0, 16.0, 8.0));
+        assert_eq!(regions["8"].src, rect(37.0, 21.0, 16.0, 8.0));
+        assert_eq!(regions["8"].dest, rect(0.0, 0.0, 16.0, 8.0));
+    }
+
+    #[test]
+    fn grid_errors() {
+        assert!(grid(r#"{ "cell": [0, 16] }"#, 32.0, 32.0).is_err());
+        let names = r#"{ "cell": [16, 16], "names": ["a", "b", "c"] }"#;
+        assert!(grid(names, 32.0, 16.0).is_err());
+    }
+
+    #[test]
+    fn grid_arithmetic_does_not_overflow() {
+        let max = u32::MAX;
+        let json = format!(
+            r#"{{ "cell": [1, 1], "margin": {0}, "spacing": {0} }}"#,
+            max
+        );
+        assert!(grid(&json, 64.0, 64.0).unwrap().is_empty());
+        let json = format!(r#"{{ "cell": [{0}, {0}], "spacing": {0} }}"#, max);
+        assert_eq!(grid(&json, 1e10, 1e10).unwrap().len(), 1);
+    }
+}
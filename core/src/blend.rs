@@ -0,0 +1,99 @@
+//! blend modes and fading for draw calls
+
+use crate::prelude::*;
+use quicksilver::golem::blend::{
+    BlendChannel, BlendEquation, BlendFactor, BlendFunction, BlendInput, BlendMode, BlendOperation,
+};
+
+/// How a draw call is combined with what's already on the screen
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Blend {
+    /// Cover what's underneath, according to alpha
+    Normal,
+    /// Add to what's underneath, which only ever gets brighter
+    Additive,
+    /// Multiply what's underneath, which only ever gets darker. Alpha is ignored.
+    Multiply,
+    /// The inverse of multiply, which only ever gets brighter
+    Screen,
+    /// Subtract from what's underneath
+    Subtract,
+}
+
+impl Default for Blend {
+    fn default() -> Self {
+        Blend::Normal
+    }
+}
+
+impl std::str::FromStr for Blend {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "normal" => Ok(Blend::Normal),
+            "additive" => Ok(Blend::Additive),
+            "multiply" => Ok(Blend::Multiply),
+            "screen" => Ok(Blend::Screen),
+            "subtract" => Ok(Blend::Subtract),
+            _ => Err(format!(
+                "unknown blend mode {:?}, expected normal, additive, multiply, screen or subtract",
+                s
+            )),
+        }
+    }
+}
+
+impl Blend {
+    fn mode(self) -> BlendMode {
+        let alpha = |is_inverse| BlendFactor::Color {
+            input: BlendInput::Source,
+            channel: BlendChannel::Alpha,
+            is_inverse,
+        };
+        let color = |input, is_inverse| BlendFactor::Color {
+            input,
+            channel: BlendChannel::Color,
+            is_inverse,
+        };
+        let (operation, source, destination) = match self {
+            Blend::Normal => (BlendOperation::Add, alpha(false), alpha(true)),
+            Blend::Additive => (BlendOperation::Add, alpha(false), BlendFactor::One),
+            Blend::Multiply => (
+                BlendOperation::Add,
+                color(BlendInput::Destination, false),
+                BlendFactor::Zero,
+            ),
+            Blend::Screen => (
+                BlendOperation::Add,
+                alpha(false),
+                color(BlendInput::Source, true),
+            ),
+            Blend::Subtract => (
+                BlendOperation::ReverseSubtract,
+                alpha(false),
+                BlendFactor::One,
+            ),
+        };
+        BlendMode {
+            equation: BlendEquation::Same(operation),
+            function: BlendFunction::Same {
+                source,
+                destination,
+            },
+            ..BlendMode::default()
+        }
+    }
+}
+
+/// Switch to `blend` if it isn't already in use, since changing it flushes the draw batch
+pub fn apply(gfx: &mut Graphics, blend: Blend, current: &mut Blend) {
+    if *current != blend {
+        gfx.set_blend_mode(Some(blend.mode()));
+        *current = blend;
+    }
+}
+
+/// Go back to normal blending, whatever state the graphics were left in
+pub fn reset(gfx: &mut Graphics) {
+    gfx.set_blend_mode(Some(Blend::Normal.mode()));
+}
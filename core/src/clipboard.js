@@ -0,0 +1,23 @@
+const results = [];
+
+export function read() {
+    // the browser may ask the player for permission, so this can take a while
+    if (navigator.clipboard && navigator.clipboard.readText) {
+        navigator.clipboard
+            .readText()
+            .then((text) => results.push(text))
+            .catch((e) => console.warn("couldn't read the clipboard:", e));
+    }
+}
+
+export function write(text) {
+    if (navigator.clipboard && navigator.clipboard.writeText) {
+        navigator.clipboard
+            .writeText(text)
+            .catch((e) => console.warn("couldn't write to the clipboard:", e));
+    }
+}
+
+export function drain() {
+    return results.splice(0, results.length);
+}
@@ -0,0 +1,106 @@
+//! the system clipboard, which the browser only lets us read asynchronously
+
+#[cfg(not(target_arch = "wasm32"))]
+mod platform {
+    use copypasta::{ClipboardContext, ClipboardProvider};
+    use std::cell::RefCell;
+
+    std::thread_local! {
+        static CLIPBOARD: RefCell<Option<ClipboardContext>> = RefCell::new(None);
+        /// Reads are instant, but are delivered like on the web so games work the same on both
+        static READS: RefCell<Vec<String>> = RefCell::new(Vec::new());
+    }
+
+    fn with_clipboard<T>(
+        f: impl FnOnce(&mut ClipboardContext) -> Result<T, Box<dyn std::error::Error + Send + Sync>>,
+    ) -> Result<T, String> {
+        CLIPBOARD.with(|c| {
+            let mut c = c.borrow_mut();
+            if c.is_none() {
+                *c = Some(ClipboardContext::new().map_err(|e| e.to_string())?);
+            }
+            f(c.as_mut().unwrap()).map_err(|e| e.to_string())
+        })
+    }
+
+    pub fn get() -> Option<String> {
+        with_clipboard(|c| c.get_contents()).ok()
+    }
+
+    pub fn set(text: &str) -> Result<(), String> {
+        with_clipboard(|c| c.set_contents(text.to_owned()))
+    }
+
+    pub fn paste() -> Option<String> {
+        get()
+    }
+
+    pub fn request() {
+        if let Some(text) = get() {
+            READS.with(|r| r.borrow_mut().push(text));
+        }
+    }
+
+    pub fn poll() -> Vec<String> {
+        READS.with(|r| r.replace(Vec::new()))
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod platform {
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen(module = "/src/clipboard.js")]
+    extern "C" {
+        fn read();
+        fn write(text: &str);
+        fn drain() -> js_sys::Array;
+    }
+
+    pub fn get() -> Option<String> {
+        None
+    }
+
+    pub fn set(text: &str) -> Result<(), String> {
+        write(text);
+        Ok(())
+    }
+
+    // pasting goes through the browser's paste event instead, see text_input.js
+    pub fn paste() -> Option<String> {
+        None
+    }
+
+    pub fn request() {
+        read();
+    }
+
+    pub fn poll() -> Vec<String> {
+        drain().iter().filter_map(|v| v.as_string()).collect()
+    }
+}
+
+/// The text on the clipboard, if it can be read right away, which it never can on the web
+pub fn get() -> Option<String> {
+    platform::get()
+}
+
+/// Start reading the clipboard, with the text showing up in `poll` once it's been read
+pub fn request() {
+    platform::request()
+}
+
+pub fn set(text: &str) -> Result<(), String> {
+    platform::set(text)
+}
+
+/// The text to insert when the paste shortcut is pressed, if the platform doesn't deliver it
+/// some other way
+pub fn paste() -> Option<String> {
+    platform::paste()
+}
+
+/// Take the results of clipboard reads that finished since the last call
+pub fn poll() -> Vec<String> {
+    platform::poll()
+}
@@ -0,0 +1,146 @@
+//! frame statistics and the debug overlay
+
+use crate::prelude::*;
+use instant::{Duration, Instant};
+
+#[derive(Default)]
+struct Counters {
+    draw_calls: usize,
+    vertices: usize,
+    update: Duration,
+    draw: Duration,
+    event: Duration,
+}
+
+pub struct Stats {
+    current: Counters,
+    last: Counters,
+    frames: u32,
+    ticks: u32,
+    window_start: Instant,
+    fps: f64,
+    tps: f64,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats {
+            current: Counters::default(),
+            last: Counters::default(),
+            frames: 0,
+            ticks: 0,
+            window_start: Instant::now(),
+            fps: 0.0,
+            tps: 0.0,
+        }
+    }
+}
+
+/// Which python callback some time was spent in
+#[derive(Clone, Copy)]
+pub enum Callback {
+    Update,
+    Draw,
+    Event,
+}
+
+impl Stats {
+    /// Record a draw call submitting `vertices` vertices
+    pub fn record_draw(&mut self, vertices: usize) {
+        self.current.draw_calls += 1;
+        self.current.vertices += vertices;
+    }
+
+    pub fn record_time(&mut self, callback: Callback, time: Duration) {
+        let total = match callback {
+            Callback::Update => &mut self.current.update,
+            Callback::Draw => &mut self.current.draw,
+            Callback::Event => &mut self.current.event,
+        };
+        *total += time;
+    }
+
+    pub fn tick(&mut self) {
+        self.ticks += 1;
+    }
+
+    /// Finish the current frame, making its counters the ones shown by the overlay
+    pub fn end_frame(&mut self) {
+        self.last = std::mem::take(&mut self.current);
+        self.frames += 1;
+
+        let elapsed = self.window_start.elapsed().as_secs_f64();
+        if elapsed >= 1.0 {
+            self.fps = self.frames as f64 / elapsed;
+            self.tps = self.ticks as f64 / elapsed;
+            self.frames = 0;
+            self.ticks = 0;
+            self.window_start = Instant::now();
+        }
+    }
+}
+
+fn ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+pub fn draw_overlay(
+    gfx: &mut Graphics,
+    resources: &mut Resources,
+    state: &crate::State,
+) -> QsResult<()> {
+    let stats = &state.stats;
+    let last = &stats.last;
+    let lines = [
+        format!("fps: {:.1}", stats.fps),
+        format!(
+            "ticks/s: {:.1} (target {:.1})",
+            stats.tps,
+            1000.0 / state.update_rate
+        ),
+        format!(
+            "python: update {:.2}ms, draw {:.2}ms, event {:.2}ms",
+            ms(last.update),
+            ms(last.draw),
+            ms(last.event)
+        ),
+        format!(
+            "draw calls: {}, vertices: {}",
+            last.draw_calls, last.vertices
+        ),
+        format!(
+            "resources: {} sprites, {} anims, {} sounds, {} fonts, {} atlases, {} tilemaps",
+            resources.imgs.len(),
+            resources.anims.len(),
+            resources.sounds.len(),
+            resources.fonts.len(),
+            resources.atlases.len(),
+            resources.tilemaps.len()
+        ),
+    ];
+
+    let (font, pt) = match resources.get_font("default") {
+        Some(f) => f,
+        None => return Ok(()),
+    };
+    let line_height = pt * 1.5;
+
+    // the overlay is drawn in window coordinates, regardless of the game's view
+    gfx.set_view(Transform::IDENTITY);
+    gfx.set_transform(Transform::IDENTITY);
+
+    let bg = Rectangle::new(
+        Vector::ZERO,
+        Vector::new(400.0, line_height * lines.len() as f32 + pt),
+    );
+    gfx.fill_rect(&bg, Color::BLACK.with_alpha(0.7));
+
+    let mut offset = Vector::new(pt / 2.0, line_height);
+    for line in lines.iter() {
+        font.draw(gfx, line, Color::WHITE, offset)?;
+        offset.y += line_height;
+    }
+
+    gfx.set_view(state.view);
+    Ok(())
+}
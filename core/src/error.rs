@@ -0,0 +1,92 @@
+//! what to do when the game raises an exception
+
+use crate::prelude::*;
+
+/// How errors from the python callbacks are handled
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ErrorMode {
+    /// Stop the game and return the error
+    Crash,
+    /// Stop the game and show the traceback in the window, with a key to reload the game
+    Screen,
+    /// Report the error and keep running, skipping the rest of the failing callback
+    Skip,
+}
+
+impl Default for ErrorMode {
+    fn default() -> Self {
+        ErrorMode::Screen
+    }
+}
+
+impl std::str::FromStr for ErrorMode {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "crash" => Ok(ErrorMode::Crash),
+            "screen" => Ok(ErrorMode::Screen),
+            "skip" => Ok(ErrorMode::Skip),
+            _ => anyhow::bail!("unknown error mode {:?}, expected crash, screen or skip", s),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn report(e: &anyhow::Error) {
+    eprintln!("{:?}", e);
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn report(e: &anyhow::Error) {
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen]
+    extern "C" {
+        #[wasm_bindgen(js_namespace = console, js_name = error)]
+        fn console_error(s: &str);
+    }
+
+    console_error(&format!("{:?}", e));
+}
+
+/// The key that reloads the game from the error screen
+pub const RELOAD_KEY: Key = Key::R;
+
+/// Show `err` in the window until the reload key is pressed
+pub async fn error_screen(
+    err: &anyhow::Error,
+    win: &Window,
+    gfx: &mut Graphics,
+    input: &mut Input,
+) -> anyhow::Result<()> {
+    report(err);
+
+    let mut font = crate::resources::default_font(gfx)?;
+    let pt = crate::resources::DEFAULT_FONT_SIZE;
+    let line_height = pt * 1.5;
+    let text = format!("{:?}", err);
+
+    loop {
+        while let Some(e) = input.next_event().await {
+            if let Event::KeyboardInput(k) = e {
+                if k.key() == RELOAD_KEY && k.is_down() {
+                    return Ok(());
+                }
+            }
+        }
+
+        gfx.set_view(Transform::IDENTITY);
+        gfx.set_transform(Transform::IDENTITY);
+        crate::blend::reset(gfx);
+        gfx.clear(Color::from_rgba(80, 0, 0, 1.0));
+
+        let mut offset = Vector::new(pt, line_height * 2.0);
+        let header = format!("The game crashed. Press {:?} to reload.", RELOAD_KEY);
+        for line in std::iter::once(header.as_str()).chain(text.lines()) {
+            font.draw(gfx, line, Color::WHITE, offset)?;
+            offset.y += line_height;
+        }
+
+        gfx.present(win)?;
+    }
+}
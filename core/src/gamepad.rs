@@ -0,0 +1,119 @@
+//! gamepad state tracking
+
+use crate::prelude::*;
+use crate::ButtonState;
+
+#[rustfmt::skip]
+static BUTTON_LIST: [GamepadButton; NUM_BUTTONS] = [
+    GamepadButton::South, GamepadButton::East, GamepadButton::North, GamepadButton::West,
+    GamepadButton::LeftShoulder, GamepadButton::LeftTrigger, GamepadButton::RightShoulder,
+    GamepadButton::RightTrigger, GamepadButton::Select, GamepadButton::Start,
+    GamepadButton::LeftStick, GamepadButton::RightStick, GamepadButton::DPadUp,
+    GamepadButton::DPadDown, GamepadButton::DPadLeft, GamepadButton::DPadRight,
+];
+const NUM_BUTTONS: usize = 16;
+
+static AXIS_LIST: [GamepadAxis; NUM_AXES] = [
+    GamepadAxis::LeftStickX,
+    GamepadAxis::LeftStickY,
+    GamepadAxis::RightStickX,
+    GamepadAxis::RightStickY,
+];
+const NUM_AXES: usize = 4;
+
+/// Xbox-style names for the face buttons, in addition to the positional ones
+const BUTTON_ALIASES: [(&str, GamepadButton); 4] = [
+    ("A", GamepadButton::South),
+    ("B", GamepadButton::East),
+    ("X", GamepadButton::West),
+    ("Y", GamepadButton::North),
+];
+
+pub const DEFAULT_DEADZONE: f32 = 0.15;
+
+pub fn parse_button(name: &str) -> Option<GamepadButton> {
+    BUTTON_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map(|(_, b)| *b)
+        .or_else(|| {
+            BUTTON_LIST
+                .iter()
+                .copied()
+                .find(|b| format!("{:?}", b) == name)
+        })
+}
+
+pub fn parse_axis(name: &str) -> Option<GamepadAxis> {
+    AXIS_LIST
+        .iter()
+        .copied()
+        .find(|a| format!("{:?}", a) == name)
+}
+
+fn button_index(button: GamepadButton) -> Option<usize> {
+    BUTTON_LIST.iter().position(|b| *b == button)
+}
+
+fn axis_index(axis: GamepadAxis) -> Option<usize> {
+    AXIS_LIST.iter().position(|a| *a == axis)
+}
+
+pub struct Gamepad {
+    pub id: GamepadId,
+    pub connected: bool,
+    buttons: [ButtonState; NUM_BUTTONS],
+    /// raw axis values, before the deadzone is applied
+    axes: [f32; NUM_AXES],
+    pub deadzone: f32,
+}
+
+impl Gamepad {
+    pub fn new(id: GamepadId, deadzone: f32) -> Self {
+        Gamepad {
+            id,
+            connected: true,
+            buttons: [ButtonState::NotPressed; NUM_BUTTONS],
+            axes: [0.0; NUM_AXES],
+            deadzone,
+        }
+    }
+
+    /// Forget about any buttons or sticks that were held when the pad was disconnected
+    pub fn reset(&mut self) {
+        self.buttons = [ButtonState::NotPressed; NUM_BUTTONS];
+        self.axes = [0.0; NUM_AXES];
+    }
+
+    pub fn button(&self, button: GamepadButton) -> ButtonState {
+        button_index(button).map_or(ButtonState::NotPressed, |i| self.buttons[i])
+    }
+
+    pub fn update_button(&mut self, button: GamepadButton, down: bool) {
+        if let Some(i) = button_index(button) {
+            self.buttons[i].update(down);
+        }
+    }
+
+    /// Settle the buttons that changed during the last tick
+    pub fn settle(&mut self) {
+        self.buttons.iter_mut().for_each(ButtonState::settle);
+    }
+
+    pub fn set_axis(&mut self, axis: GamepadAxis, value: f32) {
+        if let Some(i) = axis_index(axis) {
+            self.axes[i] = value;
+        }
+    }
+
+    /// The value of an axis from -1 to 1, with the deadzone cut out and the rest of the range
+    /// stretched to fill it
+    pub fn axis(&self, axis: GamepadAxis) -> f32 {
+        let value = axis_index(axis).map_or(0.0, |i| self.axes[i]);
+        if value.abs() < self.deadzone {
+            0.0
+        } else {
+            value.signum() * (value.abs() - self.deadzone) / (1.0 - self.deadzone)
+        }
+    }
+}
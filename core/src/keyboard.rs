@@ -0,0 +1,105 @@
+//! modifier keys and keyboard layouts
+//!
+//! Physical positions are named after the key in that spot on a US QWERTY keyboard. On the web
+//! they come straight from the browser's scancode; on desktop the layout set by the game is used
+//! to work them out, see `KEYS_ARE_POSITIONS`.
+
+use crate::prelude::*;
+use crate::ButtonState;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Layout {
+    Qwerty,
+    Azerty,
+    Qwertz,
+}
+
+/// (logical key, physical position) for every key that moves on a French AZERTY keyboard
+const AZERTY: &[(Key, Key)] = &[
+    (Key::A, Key::Q),
+    (Key::Q, Key::A),
+    (Key::Z, Key::W),
+    (Key::W, Key::Z),
+    (Key::M, Key::Semicolon),
+    (Key::Comma, Key::M),
+    (Key::Semicolon, Key::Comma),
+    (Key::Colon, Key::Period),
+];
+
+/// (logical key, physical position) for every key that moves on a German QWERTZ keyboard
+const QWERTZ: &[(Key, Key)] = &[(Key::Z, Key::Y), (Key::Y, Key::Z)];
+
+/// Whether keys are already reported by where they are, rather than by what they type
+///
+/// Browsers give every key event a `code`, the scancode named after the key in that position on a
+/// US QWERTY keyboard, and that's what the web backend turns into a `Key`, so there the layout
+/// isn't needed. Key presses the browser gives no `code` for don't reach the game at all. The
+/// desktop backends report keys by what they type and quicksilver doesn't pass their scancodes on,
+/// so there the position is worked out from the layout.
+const KEYS_ARE_POSITIONS: bool = cfg!(target_arch = "wasm32");
+
+impl Layout {
+    fn moved_keys(self) -> &'static [(Key, Key)] {
+        if KEYS_ARE_POSITIONS {
+            return &[];
+        }
+        match self {
+            Layout::Qwerty => &[],
+            Layout::Azerty => AZERTY,
+            Layout::Qwertz => QWERTZ,
+        }
+    }
+
+    /// The physical position of a key the player pressed
+    pub fn to_physical(self, key: Key) -> Key {
+        self.moved_keys()
+            .iter()
+            .find(|(logical, _)| *logical == key)
+            .map_or(key, |(_, physical)| *physical)
+    }
+
+    /// The key the player has at a physical position
+    pub fn to_logical(self, key: Key) -> Key {
+        self.moved_keys()
+            .iter()
+            .find(|(_, physical)| *physical == key)
+            .map_or(key, |(logical, _)| *logical)
+    }
+}
+
+impl std::str::FromStr for Layout {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "qwerty" => Ok(Layout::Qwerty),
+            "azerty" => Ok(Layout::Azerty),
+            "qwertz" => Ok(Layout::Qwertz),
+            _ => Err(format!(
+                "unknown keyboard layout {:?}, expected qwerty, azerty or qwertz",
+                s
+            )),
+        }
+    }
+}
+
+/// Which modifier keys are held down
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    /// The Windows, Command or Super key
+    pub logo: bool,
+}
+
+impl Modifiers {
+    pub fn from_keyboard(keyboard: &[ButtonState]) -> Self {
+        let down =
+            |l: Key, r: Key| keyboard[l as usize].is_down() || keyboard[r as usize].is_down();
+        Modifiers {
+            shift: down(Key::LShift, Key::RShift),
+            ctrl: down(Key::LControl, Key::RControl),
+            alt: down(Key::LAlt, Key::RAlt),
+            logo: down(Key::LWin, Key::RWin),
+        }
+    }
+}
@@ -1,11 +1,24 @@
 #[macro_use]
 extern crate rustpython_vm;
 
+mod actions;
 mod anim;
+mod atlas;
+mod blend;
+mod clipboard;
+mod debug;
+mod error;
+mod gamepad;
+mod keyboard;
+mod pointer;
 mod prelude;
 mod pyqs;
 mod resources;
+mod shapes;
 mod sound;
+mod text_input;
+mod tilemap;
+mod touch;
 
 use anyhow::Context;
 use instant::{Duration, Instant};
@@ -13,7 +26,16 @@ use rustpython_vm::{bytecode::FrozenModule, PySettings};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
+use crate::actions::Binding;
+use crate::blend::Blend;
+use crate::debug::{Callback, Stats};
+pub use crate::error::ErrorMode;
+use crate::gamepad::Gamepad;
+use crate::keyboard::{Layout, Modifiers};
 use crate::prelude::*;
+use crate::shapes::PathBuilder;
+use crate::text_input::{ImeEvent, TextInput};
+use crate::touch::{Touch, TouchEvent, TouchPhase};
 
 struct PickItUp {
     interp: Interpreter,
@@ -23,12 +45,23 @@ struct PickItUp {
     draw_fn: Option<PyObjectRef>,
     onload_fn: Option<PyObjectRef>,
     event_fn: Option<PyObjectRef>,
+    hooks: Hooks,
     state: PyObjectRef,
-    last_update: Instant,
+    last_frame: Instant,
+    /// Time that has passed but hasn't been simulated by an update tick yet
+    accumulator: Duration,
 
     window_initialized: bool,
 }
 
+/// Optional callbacks for changes to the window or to the lifecycle of the game
+struct Hooks {
+    resize: Option<PyObjectRef>,
+    focus: Option<PyObjectRef>,
+    blur: Option<PyObjectRef>,
+    quit: Option<PyObjectRef>,
+}
+
 fn handle_err<C>(vm: &VirtualMachine, e: PyBaseExceptionRef, ctx: C) -> anyhow::Error
 where
     C: std::fmt::Display + std::fmt::Debug + Send + Sync + 'static,
@@ -40,7 +73,7 @@ where
 }
 
 impl PickItUp {
-    async fn new(opts: InitOptions, gfx: &Graphics) -> anyhow::Result<Self> {
+    async fn new(opts: &InitOptions, gfx: &Graphics) -> anyhow::Result<Self> {
         let InitOptions {
             filename,
             frozen,
@@ -74,7 +107,7 @@ impl PickItUp {
         let interp = Interpreter::new_with_init(settings, |vm| {
             vm.add_native_module(MOD_NAME.to_owned(), Box::new(pyqs::make_module));
             if let Some(frozen) = frozen {
-                vm.add_frozen(frozen);
+                vm.add_frozen(frozen.clone());
             }
             if cfg!(target_arch = "wasm32") {
                 rustpython_vm::InitParameter::Internal
@@ -82,7 +115,7 @@ impl PickItUp {
                 rustpython_vm::InitParameter::External
             }
         });
-        let (state, sprites, update_fn, onload_fn, draw_fn, event_fn) =
+        let (state, sprites, update_fn, onload_fn, draw_fn, event_fn, hooks) =
             interp.enter(|vm| -> anyhow::Result<_> {
                 let code = match source {
                     Some(source) => vm
@@ -124,6 +157,12 @@ impl PickItUp {
                 let onload_fn = get_func("onload")?;
                 let draw_fn = get_func("draw")?;
                 let event_fn = get_func("event")?;
+                let hooks = Hooks {
+                    resize: get_func("on_resize")?,
+                    focus: get_func("on_focus")?,
+                    blur: get_func("on_blur")?,
+                    quit: get_func("on_quit")?,
+                };
 
                 Ok((
                     state,
@@ -132,6 +171,7 @@ impl PickItUp {
                     onload_fn,
                     draw_fn,
                     event_fn,
+                    hooks,
                 ))
             })?;
 
@@ -146,8 +186,10 @@ impl PickItUp {
             draw_fn,
             event_fn,
             onload_fn,
+            hooks,
             state,
-            last_update: Instant::now(),
+            last_frame: Instant::now(),
+            accumulator: Duration::default(),
             window_initialized: false,
         })
     }
@@ -161,10 +203,174 @@ impl PickItUp {
         GRAPHICS.set(gfx, || STATE.set(state, || SPRITES.set(&self.sprites, f)))
     }
 
+    /// Call a lifecycle hook if it's defined, returning `false` only if it returned `False`
+    fn call_hook(
+        &self,
+        hook: &Option<PyObjectRef>,
+        gfx: &RefCell<Graphics>,
+        state: &RefCell<State>,
+        ctx: &'static str,
+        args: impl FnOnce(&VirtualMachine) -> Vec<PyObjectRef>,
+    ) -> anyhow::Result<bool> {
+        let hook = match hook {
+            Some(hook) => hook,
+            None => return Ok(true),
+        };
+        self.set_context(gfx, state, || {
+            self.interp.enter(|vm| {
+                let mut hook_args = vec![self.state.clone()];
+                hook_args.extend(args(vm));
+                vm.invoke(hook, hook_args)
+                    .map(|ret| !ret.is(&vm.ctx.false_value))
+                    .map_err(|e| handle_err(vm, e, ctx))
+            })
+        })
+    }
+
+    fn lifecycle_event(
+        &mut self,
+        event: &Event,
+        gfx: &mut RefCell<Graphics>,
+        state: &mut RefCell<State>,
+    ) -> anyhow::Result<()> {
+        match event {
+            Event::Resized(_) => {
+                let size = state.get_mut().winsize;
+                self.call_hook(
+                    &self.hooks.resize,
+                    gfx,
+                    state,
+                    "in on_resize function",
+                    |vm| {
+                        vec![
+                            vm.ctx.new_float(size.x.into()),
+                            vm.ctx.new_float(size.y.into()),
+                        ]
+                    },
+                )?;
+            }
+            Event::FocusChanged(f) => {
+                if f.is_focused() {
+                    if std::mem::replace(&mut state.get_mut().audio_paused, false) {
+                        Sound::resume_all();
+                    }
+                    self.call_hook(
+                        &self.hooks.focus,
+                        gfx,
+                        state,
+                        "in on_focus function",
+                        |_| vec![],
+                    )?;
+                } else {
+                    if state.get_mut().pause_on_blur {
+                        Sound::pause_all();
+                        state.get_mut().audio_paused = true;
+                    }
+                    self.call_hook(
+                        &self.hooks.blur,
+                        gfx,
+                        state,
+                        "in on_blur function",
+                        |_| vec![],
+                    )?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Handle a pending `qs.quit()`, returning the exit code if the game should actually exit
+    fn check_quit(
+        &mut self,
+        gfx: &mut RefCell<Graphics>,
+        state: &mut RefCell<State>,
+    ) -> anyhow::Result<Option<i32>> {
+        let code = match state.get_mut().exit_code.take() {
+            Some(code) => code,
+            None => return Ok(None),
+        };
+        let proceed = self.call_hook(
+            &self.hooks.quit,
+            gfx,
+            state,
+            "in on_quit function",
+            |_| vec![],
+        )?;
+        Ok(if proceed { Some(code) } else { None })
+    }
+
+    /// Run the game's `atexit` handlers and flush its output, so nothing it meant to save is
+    /// lost, then stop the audio
+    fn shutdown(&self, gfx: &RefCell<Graphics>, state: &RefCell<State>) {
+        let res = self.set_context(gfx, state, || {
+            self.interp.enter(|vm| {
+                let code = vm
+                    .compile(FLUSH_SOURCE, compile::Mode::Exec, "<shutdown>".to_owned())
+                    .context("couldn't compile the shutdown code")?;
+                vm.run_code_obj(code, vm.new_scope_with_builtins())
+                    .map_err(|e| handle_err(vm, e, "while shutting down"))?;
+                Ok(())
+            })
+        });
+        if let Err(e) = res {
+            error::report(&e);
+        }
+        Sound::stop_all();
+    }
+
     fn event(&mut self, event: &Event, state: &mut RefCell<State>) -> anyhow::Result<()> {
+        self.dispatch_event(state, |vm, state| event_to_py(vm, event, state))
+    }
+
+    fn motion_event(&mut self, motion: Vector, state: &mut RefCell<State>) -> anyhow::Result<()> {
+        self.dispatch_event(state, |vm, _| Some(motion_to_py(vm, motion)))
+    }
+
+    fn clipboard_event(&mut self, text: &str, state: &mut RefCell<State>) -> anyhow::Result<()> {
+        self.dispatch_event(state, |vm, _| Some(clipboard_to_py(vm, text)))
+    }
+
+    fn ime_event(&mut self, ime: &ImeEvent, state: &mut RefCell<State>) -> anyhow::Result<()> {
+        self.dispatch_event(state, |vm, _| ime_to_py(vm, ime))
+    }
+
+    /// Tell python about changes to the text input buffer since the last call
+    fn text_input_event(&mut self, state: &mut RefCell<State>) -> anyhow::Result<()> {
+        match &mut state.get_mut().text_input {
+            Some(t) if t.changed => t.changed = false,
+            _ => return Ok(()),
+        }
+        self.dispatch_event(state, |vm, state| {
+            state.text_input.as_ref().map(|t| text_input_to_py(vm, t))
+        })
+    }
+
+    fn touch_event(
+        &mut self,
+        touch: &TouchEvent,
+        emulated_mouse: bool,
+        state: &mut RefCell<State>,
+    ) -> anyhow::Result<()> {
+        self.dispatch_event(state, |vm, state| touch_to_py(vm, touch, state))?;
+        if emulated_mouse {
+            self.dispatch_event(state, |vm, state| {
+                Some(emulated_mouse_to_py(vm, touch.phase, state))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Pass an event to the python event function, if the event is relevant to python
+    fn dispatch_event(
+        &mut self,
+        state: &mut RefCell<State>,
+        to_py: impl FnOnce(&VirtualMachine, &State) -> Option<PyObjectRef>,
+    ) -> anyhow::Result<()> {
         if let Some(event_fn) = &self.event_fn {
+            let start = Instant::now();
             self.interp.enter(|vm| -> anyhow::Result<()> {
-                if let Some(evt) = event_to_py(vm, event, state.get_mut()) {
+                if let Some(evt) = to_py(vm, state.get_mut()) {
                     STATE.set(state, || {
                         SPRITES.set(&self.sprites, || {
                             vm.invoke(event_fn, vec![self.state.clone(), evt])
@@ -173,7 +379,11 @@ impl PickItUp {
                     })?;
                 }
                 Ok(())
-            })?
+            })?;
+            state
+                .get_mut()
+                .stats
+                .record_time(Callback::Event, start.elapsed());
         }
 
         Ok(())
@@ -185,31 +395,81 @@ impl PickItUp {
         state: &mut RefCell<State>,
     ) -> anyhow::Result<()> {
         if !self.window_initialized {
-            if let Some(onload_fn) = &self.onload_fn {
-                self.set_context(gfx, state, || {
+            let res = match &self.onload_fn {
+                Some(onload_fn) => self.set_context(gfx, state, || {
                     self.interp.enter(|vm| {
                         // invoke onload_fn
                         vm.invoke(onload_fn, vec![self.state.clone()])
                             .map(drop)
                             .map_err(|e| handle_err(vm, e, "in onload function"))
                     })
-                })?
-            }
+                }),
+                None => Ok(()),
+            };
+            // even if onload failed, so skipping the error doesn't run it again every frame
             self.window_initialized = true;
+            // don't count loading time towards the first frame
+            self.last_frame = Instant::now();
+            res?;
         }
 
-        let update_rate = state.get_mut().update_rate;
-        let period = Duration::from_secs_f64(update_rate / 1000.0);
-        if self.last_update.elapsed() >= period {
-            self.last_update += period;
-        } else {
+        let now = Instant::now();
+        let frame_time = now - self.last_frame;
+        self.last_frame = now;
+
+        if state.get_mut().is_paused() {
             return Ok(());
         }
 
+        match state.get_mut().timestep {
+            Timestep::Variable => {
+                state.get_mut().frame_alpha = 1.0;
+                self.tick(gfx, state, frame_time.as_secs_f64() * 1000.0)?;
+            }
+            Timestep::Fixed => {
+                self.accumulator += frame_time;
+                let mut steps = 0;
+                loop {
+                    let s = state.get_mut();
+                    let period = Duration::from_secs_f64(s.update_rate / 1000.0);
+                    if self.accumulator < period {
+                        s.frame_alpha = self.accumulator.as_secs_f64() / period.as_secs_f64();
+                        break;
+                    }
+                    if steps >= s.max_catch_up {
+                        // we're too far behind to catch up; drop the backlog instead of
+                        // playing it out over the next few frames
+                        let backlog = self.accumulator.as_secs_f64() % period.as_secs_f64();
+                        self.accumulator = Duration::from_secs_f64(backlog);
+                        continue;
+                    }
+                    self.accumulator -= period;
+                    let update_rate = s.update_rate;
+                    self.tick(gfx, state, update_rate)?;
+                    steps += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Run a single update tick `delta` milliseconds long, before applying the time scale
+    fn tick(
+        &mut self,
+        gfx: &mut RefCell<Graphics>,
+        state: &mut RefCell<State>,
+        delta: f64,
+    ) -> anyhow::Result<()> {
+        let s = state.get_mut();
+        s.delta = delta * s.time_scale;
+        s.time += s.delta / 1000.0;
+        s.tick_count += 1;
+
         // update animations
-        self.sprites.get_mut().update_anim(update_rate);
+        self.sprites.get_mut().update_anim(s.delta);
 
         if let Some(update_fn) = &self.update_fn {
+            let start = Instant::now();
             self.set_context(gfx, state, || {
                 self.interp.enter(|vm| {
                     vm.invoke(update_fn, vec![self.state.clone()])
@@ -217,26 +477,58 @@ impl PickItUp {
                         .map_err(|e| handle_err(vm, e, "in update function"))
                 })
             })?;
+            state
+                .get_mut()
+                .stats
+                .record_time(Callback::Update, start.elapsed());
         }
+        let s = state.get_mut();
+        s.end_tick();
+        s.stats.tick();
         Ok(())
     }
 
     fn draw(
         &mut self,
+        win: &Window,
         gfx: &mut RefCell<Graphics>,
         state: &mut RefCell<State>,
     ) -> anyhow::Result<()> {
+        let s = state.get_mut();
+        // the error screen or a forgotten pop_alpha shouldn't affect this frame
+        blend::reset(gfx.get_mut());
+        s.blend = Blend::Normal;
+        s.alpha_stack.clear();
         gfx.get_mut().clear(Color::BLACK);
 
         if let Some(draw_fn) = &self.draw_fn {
-            self.set_context(gfx, state, || {
-                self.interp.enter(|vm| {
-                    vm.invoke(draw_fn, vec![self.state.clone()])
-                        .map(drop)
-                        .map_err(|e| handle_err(vm, e, "in draw function"))
+            let start = Instant::now();
+            // surfaces need the window to send what was drawn before them to the screen
+            let res = WINDOW.set(win, || {
+                self.set_context(gfx, state, || {
+                    self.interp.enter(|vm| {
+                        vm.invoke(draw_fn, vec![self.state.clone()])
+                            .map(drop)
+                            .map_err(|e| handle_err(vm, e, "in draw function"))
+                    })
                 })
-            })?;
+            });
+            // finish off a surface that was still being drawn to, even if draw failed
+            pyqs::draw_to_window(gfx.get_mut(), self.sprites.get_mut(), state.get_mut())?;
+            res?;
+            state
+                .get_mut()
+                .stats
+                .record_time(Callback::Draw, start.elapsed());
         }
+
+        let state = state.get_mut();
+        blend::apply(gfx.get_mut(), Blend::Normal, &mut state.blend);
+        if state.debug_overlay {
+            debug::draw_overlay(gfx.get_mut(), self.sprites.get_mut(), state)?;
+        }
+        state.stats.end_frame();
+        state.frame_count += 1;
         Ok(())
     }
 }
@@ -260,11 +552,32 @@ fn event_to_py(vm: &VirtualMachine, event: &Event, state: &State) -> Option<PyOb
                 }
             );
         }
+        Event::Resized(r) => {
+            set!(event, "resized");
+            set!(w, r.size().x);
+            set!(h, r.size().y);
+        }
         Event::KeyboardInput(k) => {
             let key = k.key();
             set!(event, "key");
-            set!(key, format!("{:?}", key));
-            set!(state, format!("{:?}", state.keyboard[key as usize]));
+            set!(key, pyqs::key_name(key));
+            set!(
+                physical,
+                pyqs::key_name(state.keyboard_layout.to_physical(key))
+            );
+            set!(state, state.keyboard[key as usize].name());
+            // a key that was already down is being repeated by the OS
+            set!(
+                repeat,
+                k.is_down() && state.keyboard[key as usize] == ButtonState::Held
+            );
+            let mods = state.modifiers();
+            set!(shift, mods.shift);
+            set!(ctrl, mods.ctrl);
+            set!(alt, mods.alt);
+            // `super` is a keyword in rust, so it can't go through set!
+            vm.set_attr(&d, "super", vm.ctx.new_bool(mods.logo))
+                .unwrap();
         }
         Event::ReceivedCharacter(c) => {
             set!(event, "typed");
@@ -281,22 +594,34 @@ fn event_to_py(vm: &VirtualMachine, event: &Event, state: &State) -> Option<PyOb
         Event::PointerLeft(_) => {
             set!(event, "mouse_exited");
         }
-        Event::ScrollInput(_) => {
+        Event::ScrollInput(delta) => {
+            let v = scroll_vector(delta);
             set!(event, "mouse_wheel");
-            set!(x, state.wheel_delta.x);
-            set!(y, state.wheel_delta.y);
+            set!(x, v.x);
+            set!(y, v.y);
         }
         Event::PointerInput(evt) => {
-            let i = match evt.button() {
-                MouseButton::Left => 0,
-                MouseButton::Middle => 1,
-                MouseButton::Right => 2,
-                MouseButton::Other(_) => return None,
-            };
+            let i = mouse_index(evt.button())?;
             set!(event, "mouse_button");
             set!(button, format!("{:?}", evt.button()));
             set!(down, evt.is_down());
-            set!(state, format!("{:?}", state.mouse[i]));
+            set!(state, state.mouse[i].name());
+        }
+        Event::GamepadConnected(g) => {
+            set!(event, "gamepad_connected");
+            set!(gamepad, state.gamepad_index(g.gamepad())?);
+        }
+        Event::GamepadDisconnected(g) => {
+            set!(event, "gamepad_disconnected");
+            set!(gamepad, state.gamepad_index(g.gamepad())?);
+        }
+        Event::GamepadButton(b) => {
+            let idx = state.gamepad_index(b.gamepad())?;
+            set!(event, "gamepad_button");
+            set!(gamepad, idx);
+            set!(button, format!("{:?}", b.button()));
+            set!(down, b.is_down());
+            set!(state, state.gamepads[idx].button(b.button()).name());
         }
         // TODO: more events
         _ => return None,
@@ -304,12 +629,137 @@ fn event_to_py(vm: &VirtualMachine, event: &Event, state: &State) -> Option<PyOb
     Some(d)
 }
 
+fn touch_to_py(vm: &VirtualMachine, touch: &TouchEvent, state: &State) -> Option<PyObjectRef> {
+    let d = vm.ctx.new_namespace();
+    macro_rules! set {
+        ($key:ident, $val:expr) => {
+            vm.set_attr(&d, stringify!($key), IntoPyObject::into_pyobject($val, vm))
+                .unwrap();
+        };
+    };
+    let pos = state.touches.iter().find(|t| t.id == touch.id)?.pos;
+    set!(event, "touch");
+    set!(id, touch.id);
+    set!(phase, touch.phase.name());
+    set!(x, pos.x);
+    set!(y, pos.y);
+    Some(d)
+}
+
+/// All of the mouse motion from one frame, which keeps going while the pointer is locked
+fn motion_to_py(vm: &VirtualMachine, motion: Vector) -> PyObjectRef {
+    let d = vm.ctx.new_namespace();
+    macro_rules! set {
+        ($key:ident, $val:expr) => {
+            vm.set_attr(&d, stringify!($key), IntoPyObject::into_pyobject($val, vm))
+                .unwrap();
+        };
+    };
+    set!(event, "mouse_motion");
+    set!(dx, motion.x);
+    set!(dy, motion.y);
+    d
+}
+
+/// A clipboard read that was started by `qs.request_clipboard()` has finished
+fn clipboard_to_py(vm: &VirtualMachine, text: &str) -> PyObjectRef {
+    let d = vm.ctx.new_namespace();
+    macro_rules! set {
+        ($key:ident, $val:expr) => {
+            vm.set_attr(&d, stringify!($key), IntoPyObject::into_pyobject($val, vm))
+                .unwrap();
+        };
+    };
+    set!(event, "clipboard");
+    set!(text, text);
+    d
+}
+
+fn ime_to_py(vm: &VirtualMachine, ime: &ImeEvent) -> Option<PyObjectRef> {
+    let d = vm.ctx.new_namespace();
+    macro_rules! set {
+        ($key:ident, $val:expr) => {
+            vm.set_attr(&d, stringify!($key), IntoPyObject::into_pyobject($val, vm))
+                .unwrap();
+        };
+    };
+    let (phase, text) = match ime {
+        ImeEvent::Start => ("start", ""),
+        ImeEvent::Update(text) => ("update", text.as_str()),
+        ImeEvent::End(text) => ("end", text.as_str()),
+        // shows up as a text_input event instead
+        ImeEvent::Paste(_) => return None,
+    };
+    set!(event, "composition");
+    set!(phase, phase);
+    set!(text, text);
+    Some(d)
+}
+
+fn text_input_to_py(vm: &VirtualMachine, input: &TextInput) -> PyObjectRef {
+    let d = vm.ctx.new_namespace();
+    macro_rules! set {
+        ($key:ident, $val:expr) => {
+            vm.set_attr(&d, stringify!($key), IntoPyObject::into_pyobject($val, vm))
+                .unwrap();
+        };
+    };
+    set!(event, "text_input");
+    set!(value, input.value());
+    set!(cursor, input.cursor());
+    d
+}
+
+/// The index of a button in `State::mouse`
+fn mouse_index(button: MouseButton) -> Option<usize> {
+    match button {
+        MouseButton::Left => Some(0),
+        MouseButton::Middle => Some(1),
+        MouseButton::Right => Some(2),
+        MouseButton::Other(_) => None,
+    }
+}
+
+fn scroll_vector(delta: &quicksilver::input::ScrollDelta) -> Vector {
+    use quicksilver::input::ScrollDelta;
+    match delta {
+        ScrollDelta::Lines(v) | ScrollDelta::Pixels(v) => Vector::new(v.x, v.y),
+    }
+}
+
+/// The mouse event a touch turns into when it's emulating the mouse
+fn emulated_mouse_to_py(vm: &VirtualMachine, phase: TouchPhase, state: &State) -> PyObjectRef {
+    let d = vm.ctx.new_namespace();
+    macro_rules! set {
+        ($key:ident, $val:expr) => {
+            vm.set_attr(&d, stringify!($key), IntoPyObject::into_pyobject($val, vm))
+                .unwrap();
+        };
+    };
+    if phase == TouchPhase::Move {
+        set!(event, "mouse_moved");
+        set!(x, state.mouse_pos.x);
+        set!(y, state.mouse_pos.y);
+    } else {
+        set!(event, "mouse_button");
+        set!(button, "Left");
+        set!(down, phase == TouchPhase::Start);
+        set!(state, state.mouse[0].name());
+    }
+    d
+}
+
 pub struct InitOptions {
     pub width: i32,
     pub height: i32,
     pub filename: Option<PathBuf>,
     pub frozen: Option<HashMap<String, FrozenModule>>,
     pub entry_module: Option<String>,
+    /// Whether F3 toggles the debug overlay
+    pub debug_hotkey: bool,
+    pub on_error: ErrorMode,
+    /// Called with the code passed to `qs.quit()` once the game has shut down
+    pub on_exit: Option<fn(i32)>,
 }
 impl Default for InitOptions {
     fn default() -> Self {
@@ -319,10 +769,23 @@ impl Default for InitOptions {
             filename: None,
             frozen: None,
             entry_module: None,
+            debug_hotkey: false,
+            on_error: ErrorMode::default(),
+            on_exit: None,
         }
     }
 }
 
+/// How update ticks are scheduled
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Timestep {
+    /// Update every `update_rate` milliseconds, running several updates in one frame to catch up
+    /// if needed
+    Fixed,
+    /// Update exactly once per frame, with a tick as long as the frame took
+    Variable,
+}
+
 /// The current state of a button
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
 #[repr(u8)]
@@ -348,6 +811,25 @@ impl ButtonState {
         *self = new;
     }
 
+    /// Move on from an edge state once it has been visible for a whole tick
+    fn settle(&mut self) {
+        *self = match *self {
+            ButtonState::Pressed => ButtonState::Held,
+            ButtonState::Released => ButtonState::NotPressed,
+            other => other,
+        };
+    }
+
+    /// The name python sees for the state
+    fn name(self) -> &'static str {
+        match self {
+            ButtonState::Pressed => "Pressed",
+            ButtonState::Held => "Held",
+            ButtonState::Released => "Released",
+            ButtonState::NotPressed => "NotPressed",
+        }
+    }
+
     /// Determine if the button is either Pressed or Held
     fn is_down(&self) -> bool {
         match *self {
@@ -359,43 +841,272 @@ impl ButtonState {
 
 pub struct State {
     update_rate: f64,
+    timestep: Timestep,
+    /// The most update ticks that will be run in a single frame
+    max_catch_up: u32,
+    /// How far between the last update tick and the next one the current frame is, from 0 to 1
+    frame_alpha: f64,
+    /// The length of the current update tick in milliseconds, after applying the time scale
+    delta: f64,
+    /// How fast game time passes relative to real time
+    time_scale: f64,
+    /// Game time since the first tick, in seconds
+    time: f64,
+    tick_count: u64,
+    frame_count: u64,
     keyboard: Box<[ButtonState; pyqs::NUM_KEYS]>,
+    keyboard_layout: Layout,
     mouse: [ButtonState; 3],
+    /// Every gamepad that has been connected, indexed by the order they connected in
+    gamepads: Vec<Gamepad>,
+    /// The deadzone given to newly connected gamepads
+    gamepad_deadzone: f32,
     mouse_pos: Vector,
+    /// How far the wheel has scrolled since the last tick
     wheel_delta: Vector,
+    /// How far the mouse has moved since the last tick, in window pixels
+    mouse_delta: Vector,
+    /// How far the mouse has moved since the last `mouse_motion` event
+    frame_motion: Vector,
+    /// The last position from `PointerMoved`, in window coordinates
+    last_pointer: Option<Vector>,
+    /// Set by `qs.set_pointer_lock()`, and applied to the window once a frame
+    pointer_lock: bool,
+    /// The line being edited between `qs.start_text_input()` and `qs.stop_text_input()`
+    text_input: Option<TextInput>,
+    /// The path being built by `qs.move_to()`, `qs.line_to()` and friends
+    path: PathBuilder,
+    /// The blend mode the graphics are currently set to
+    blend: Blend,
+    /// The surface set by `qs.draw_to()`, or None when drawing to the window
+    target: Option<String>,
+    /// The view set while drawing to `target`
+    target_view: Transform,
+    /// The size of the window's coordinate system, which doesn't change when it's resized
+    camera_size: Vector,
+    /// Pushed by `qs.push_alpha()`, and multiplied together to fade each draw call
+    alpha_stack: Vec<f32>,
+    /// Inputs bound to each named action
+    actions: HashMap<String, Vec<Binding>>,
+    /// Touch points that are on the screen, or were lifted this frame
+    touches: Vec<Touch>,
+    /// Whether the first finger down acts as the left mouse button
+    emulate_mouse: bool,
+    /// The touch that is currently emulating the mouse
+    primary_touch: Option<i64>,
     winsize: Vector,
+    focused: bool,
+    /// Whether to stop update ticks and audio while the window is unfocused
+    pause_on_blur: bool,
+    /// Whether losing focus paused the audio, so regaining it only resumes what was paused
+    audio_paused: bool,
+    /// Set by `qs.quit()`, checked after the update ticks
+    exit_code: Option<i32>,
+    view: Transform,
+    debug_overlay: bool,
+    stats: Stats,
 }
 
 impl State {
+    fn new(win: &Window) -> Self {
+        State {
+            update_rate: 1000.0 / 60.0,
+            timestep: Timestep::Fixed,
+            max_catch_up: 5,
+            frame_alpha: 0.0,
+            delta: 0.0,
+            time_scale: 1.0,
+            time: 0.0,
+            tick_count: 0,
+            frame_count: 0,
+            keyboard: Box::new([ButtonState::NotPressed; pyqs::NUM_KEYS]),
+            keyboard_layout: Layout::Qwerty,
+            mouse: [ButtonState::NotPressed; 3],
+            gamepads: Vec::new(),
+            gamepad_deadzone: gamepad::DEFAULT_DEADZONE,
+            mouse_pos: Vector::ZERO,
+            wheel_delta: Vector::ZERO,
+            mouse_delta: Vector::ZERO,
+            frame_motion: Vector::ZERO,
+            last_pointer: None,
+            pointer_lock: false,
+            text_input: None,
+            path: PathBuilder::default(),
+            blend: Blend::Normal,
+            target: None,
+            target_view: Transform::IDENTITY,
+            camera_size: win.size(),
+            alpha_stack: Vec::new(),
+            actions: HashMap::new(),
+            touches: Vec::new(),
+            emulate_mouse: false,
+            primary_touch: None,
+            winsize: win.size(),
+            focused: true,
+            pause_on_blur: false,
+            audio_paused: false,
+            exit_code: None,
+            view: Transform::IDENTITY,
+            debug_overlay: false,
+            stats: Stats::default(),
+        }
+    }
+
+    fn gamepad_index(&self, id: &GamepadId) -> Option<usize> {
+        self.gamepads.iter().position(|pad| pad.id == *id)
+    }
+
+    fn gamepad_mut(&mut self, id: &GamepadId) -> Option<&mut Gamepad> {
+        self.gamepads.iter_mut().find(|pad| pad.id == *id)
+    }
+
+    /// Apply a touch event, returning whether it moved or clicked the emulated mouse
+    fn process_touch(&mut self, t: &TouchEvent, gfx: &Graphics, win: &Window) -> bool {
+        let pos = gfx.screen_to_camera(win, t.location);
+        match self.touches.iter_mut().find(|touch| touch.id == t.id) {
+            Some(touch) => {
+                touch.pos = pos;
+                touch.phase = t.phase;
+            }
+            None => self.touches.push(Touch {
+                id: t.id,
+                pos,
+                phase: t.phase,
+            }),
+        }
+
+        if !self.emulate_mouse {
+            return false;
+        }
+        if t.phase == TouchPhase::Start && self.primary_touch.is_none() {
+            self.primary_touch = Some(t.id);
+        }
+        if self.primary_touch != Some(t.id) {
+            return false;
+        }
+        self.mouse_pos = pos;
+        match t.phase {
+            TouchPhase::Start => self.mouse[0].update(true),
+            TouchPhase::Move => {}
+            TouchPhase::End | TouchPhase::Cancel => {
+                self.mouse[0].update(false);
+                self.primary_touch = None;
+            }
+        }
+        true
+    }
+
+    fn modifiers(&self) -> Modifiers {
+        Modifiers::from_keyboard(&*self.keyboard)
+    }
+
+    fn alpha(&self) -> f32 {
+        self.alpha_stack.iter().product()
+    }
+
+    fn is_paused(&self) -> bool {
+        self.pause_on_blur && !self.focused
+    }
+
+    /// Called after every update tick, so pressed and released buttons only stay that way for
+    /// the one tick that saw them change, and the wheel delta only covers a single tick
+    fn end_tick(&mut self) {
+        self.keyboard.iter_mut().for_each(ButtonState::settle);
+        self.mouse.iter_mut().for_each(ButtonState::settle);
+        self.gamepads.iter_mut().for_each(Gamepad::settle);
+        self.wheel_delta = Vector::ZERO;
+        self.mouse_delta = Vector::ZERO;
+    }
+
+    fn process_ime(&mut self, e: &ImeEvent) {
+        let input = match &mut self.text_input {
+            Some(input) => input,
+            None => return,
+        };
+        match e {
+            ImeEvent::Start => input.composition.clear(),
+            ImeEvent::Update(text) => input.composition = text.clone(),
+            ImeEvent::End(text) => {
+                input.composition.clear();
+                input.insert(text);
+            }
+            ImeEvent::Paste(text) => input.insert(text),
+        }
+    }
+
+    fn add_motion(&mut self, motion: Vector) {
+        self.mouse_delta += motion;
+        self.frame_motion += motion;
+    }
+
     fn process_event(&mut self, e: &Event, gfx: &Graphics, win: &Window) {
         match e {
             Event::KeyboardInput(k) => {
                 self.keyboard[k.key() as usize].update(k.is_down());
+                let mods = self.modifiers();
+                if let (Some(input), true) = (&mut self.text_input, k.is_down()) {
+                    input.key(k.key(), mods);
+                }
+            }
+            Event::ReceivedCharacter(c) => {
+                let mods = self.modifiers();
+                // shortcuts, except for AltGr which some layouts need for ordinary characters
+                let shortcut = (mods.ctrl && !mods.alt) || mods.logo;
+                if let (Some(input), false) = (&mut self.text_input, shortcut) {
+                    input.insert(&c.character().to_string());
+                }
             }
             Event::PointerEntered(_) => {}
-            Event::PointerLeft(_) => {}
             Event::PointerMoved(p) => {
                 self.mouse_pos = gfx.screen_to_camera(win, p.location());
+                if let Some(last) = self.last_pointer.replace(p.location()) {
+                    if pointer::motion_from_events() {
+                        self.add_motion(p.location() - last);
+                    }
+                }
+            }
+            Event::PointerLeft(_) => {
+                // the pointer may come back somewhere else entirely
+                self.last_pointer = None;
             }
             Event::PointerInput(p) => {
-                let i = match p.button() {
-                    MouseButton::Left => 0,
-                    MouseButton::Middle => 1,
-                    MouseButton::Right => 2,
-                    MouseButton::Other(_) => return,
-                };
-                self.mouse[i].update(p.is_down());
+                if let Some(i) = mouse_index(p.button()) {
+                    self.mouse[i].update(p.is_down());
+                }
             }
             Event::ScrollInput(delta) => {
-                use quicksilver::input::ScrollDelta;
-                let v = match delta {
-                    ScrollDelta::Lines(v) | ScrollDelta::Pixels(v) => v,
-                };
-                self.wheel_delta = Vector::new(v.x, v.y);
+                self.wheel_delta += scroll_vector(delta);
             }
             Event::Resized(r) => {
                 self.winsize = r.size();
             }
+            Event::FocusChanged(f) => {
+                self.focused = f.is_focused();
+            }
+            Event::GamepadConnected(g) => match self.gamepad_mut(g.gamepad()) {
+                // a pad that reconnects gets its old index back
+                Some(pad) => pad.connected = true,
+                None => {
+                    let pad = Gamepad::new(g.gamepad().clone(), self.gamepad_deadzone);
+                    self.gamepads.push(pad);
+                }
+            },
+            Event::GamepadDisconnected(g) => {
+                if let Some(pad) = self.gamepad_mut(g.gamepad()) {
+                    pad.connected = false;
+                    pad.reset();
+                }
+            }
+            Event::GamepadButton(b) => {
+                if let Some(pad) = self.gamepad_mut(b.gamepad()) {
+                    pad.update_button(b.button(), b.is_down());
+                }
+            }
+            Event::GamepadAxis(a) => {
+                if let Some(pad) = self.gamepad_mut(a.gamepad()) {
+                    pad.set_axis(a.axis(), a.value());
+                }
+            }
             _ => {}
         }
     }
@@ -407,31 +1118,163 @@ async fn app(
     gfx: Graphics,
     mut input: Input,
 ) -> anyhow::Result<()> {
-    let mut pickitup = PickItUp::new(opts, &gfx).await?;
     let mut gfx = RefCell::new(gfx);
-    let mut state = RefCell::new(State {
-        update_rate: 1000.0 / 60.0,
-        keyboard: Box::new([ButtonState::NotPressed; pyqs::NUM_KEYS]),
-        mouse: [ButtonState::NotPressed; 3],
-        mouse_pos: Vector::ZERO,
-        wheel_delta: Vector::ZERO,
-        winsize: win.size(),
-    });
+    let code = loop {
+        let err = match run_game(&opts, &win, &mut gfx, &mut input).await {
+            Ok(code) => break code,
+            Err(err) => err,
+        };
+        Sound::stop_all();
+        if opts.on_error == ErrorMode::Crash {
+            return Err(err);
+        }
+        // the game can't continue, even if we're skipping errors; show them until a reload
+        error::error_screen(&err, &win, gfx.get_mut(), &mut input).await?;
+    };
+    if let Some(on_exit) = opts.on_exit {
+        on_exit(code);
+    }
+    Ok(())
+}
+
+/// What `PickItUp::shutdown` runs, the same things python itself does before exiting
+const FLUSH_SOURCE: &str = "\
+import atexit, sys
+atexit._run_exitfuncs()
+sys.stdout.flush()
+sys.stderr.flush()
+";
+
+/// Treats the game being dropped as the window closing, which is the only sign of it we get:
+/// quicksilver ends the event loop and drops the game while it's waiting for events
+///
+/// Only armed around those waits, and disarmed with `mem::forget` once they finish.
+struct CloseGuard<'a> {
+    pickitup: &'a PickItUp,
+    gfx: &'a RefCell<Graphics>,
+    state: &'a RefCell<State>,
+}
+
+impl Drop for CloseGuard<'_> {
+    fn drop(&mut self) {
+        // the window is already closing, so `on_quit` returning `False` can't cancel it
+        let hooks = &self.pickitup.hooks;
+        let res = self.pickitup.call_hook(
+            &hooks.quit,
+            self.gfx,
+            self.state,
+            "in on_quit function",
+            |_| vec![],
+        );
+        if let Err(e) = res {
+            error::report(&e);
+        }
+        self.pickitup.shutdown(self.gfx, self.state);
+    }
+}
+
+/// Apply the error mode to the result of a python callback
+fn recover(mode: ErrorMode, res: anyhow::Result<()>) -> anyhow::Result<()> {
+    match res {
+        Err(e) if mode == ErrorMode::Skip => {
+            error::report(&e);
+            Ok(())
+        }
+        res => res,
+    }
+}
+
+/// Load the game from scratch and run it until it quits or fails, returning the exit code
+async fn run_game(
+    opts: &InitOptions,
+    win: &Window,
+    gfx: &mut RefCell<Graphics>,
+    input: &mut Input,
+) -> anyhow::Result<i32> {
+    let debug_hotkey = opts.debug_hotkey;
+    let mode = opts.on_error;
+    let mut pickitup = PickItUp::new(opts, gfx.get_mut()).await?;
+    let mut state = RefCell::new(State::new(win));
+    // in case the last run crashed while text was being entered
+    text_input::set_active(false);
 
     loop {
-        while let Some(e) = input.next_event().await {
-            state.get_mut().process_event(&e, gfx.get_mut(), &win);
-            pickitup.event(&e, &mut state)?;
+        loop {
+            let guard = CloseGuard {
+                pickitup: &pickitup,
+                gfx,
+                state: &state,
+            };
+            let e = input.next_event().await;
+            std::mem::forget(guard);
+            let e = match e {
+                Some(e) => e,
+                None => break,
+            };
+            state.get_mut().process_event(&e, gfx.get_mut(), win);
+            if debug_hotkey {
+                let state = state.get_mut();
+                if let Event::KeyboardInput(k) = &e {
+                    if k.key() == Key::F3
+                        && state.keyboard[Key::F3 as usize] == ButtonState::Pressed
+                    {
+                        state.debug_overlay = !state.debug_overlay;
+                    }
+                }
+            }
+            recover(mode, pickitup.lifecycle_event(&e, gfx, &mut state))?;
+            recover(mode, pickitup.event(&e, &mut state))?;
+        }
+
+        // touches that ended last frame have been seen by now
+        state.get_mut().touches.retain(|t| !t.phase.is_finished());
+        let emulate_mouse = state.get_mut().emulate_mouse;
+        for t in touch::poll(emulate_mouse) {
+            let emulated = state.get_mut().process_touch(&t, gfx.get_mut(), win);
+            recover(mode, pickitup.touch_event(&t, emulated, &mut state))?;
+        }
+
+        for text in clipboard::poll() {
+            recover(mode, pickitup.clipboard_event(&text, &mut state))?;
+        }
+        for e in text_input::poll() {
+            state.get_mut().process_ime(&e);
+            recover(mode, pickitup.ime_event(&e, &mut state))?;
         }
+        recover(mode, pickitup.text_input_event(&mut state))?;
 
-        pickitup.update(&mut gfx, &mut state)?;
+        let s = state.get_mut();
+        s.add_motion(pointer::poll());
+        let motion = std::mem::replace(&mut s.frame_motion, Vector::ZERO);
+        if motion != Vector::ZERO {
+            recover(mode, pickitup.motion_event(motion, &mut state))?;
+        }
 
-        pickitup.draw(&mut gfx, &mut state)?;
+        recover(mode, pickitup.update(gfx, &mut state))?;
 
-        gfx.get_mut().present(&win)?;
+        match pickitup.check_quit(gfx, &mut state) {
+            Ok(Some(code)) => {
+                pickitup.shutdown(gfx, &state);
+                return Ok(code);
+            }
+            res => recover(mode, res.map(drop))?,
+        }
+
+        pointer::apply(win, state.get_mut().pointer_lock);
+
+        recover(mode, pickitup.draw(win, gfx, &mut state))?;
+
+        gfx.get_mut().present(win)?;
     }
 }
 
+/// Run the game until it quits with `qs.quit()` or its window is closed
+///
+/// Either way `on_quit` is called, the game's `atexit` handlers run and its output is flushed.
+/// Closing the window can't be cancelled, so `on_quit`'s return value is ignored then. This never
+/// returns, because quicksilver owns the event loop; `InitOptions::on_exit` gets the code passed
+/// to `qs.quit()` instead. On desktop the process exits with status 0 if `on_exit` returns or the
+/// window was closed.
 pub fn run(opts: InitOptions) -> ! {
     let size = Vector::new(opts.width as f32, opts.height as f32);
     let mut settings = quicksilver::Settings::default();
@@ -439,3 +1282,49 @@ pub fn run(opts: InitOptions) -> ! {
     settings.title = "pickitup";
     quicksilver::run(settings, |w, gfx, input| app(opts, w, gfx, input))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ButtonState::{self, *};
+
+    /// Feed `ticks` worth of button events through a button, settling it at the end of each
+    /// tick like `State::end_tick` does, and collect what the game would see in each tick
+    fn run(ticks: &[&[bool]]) -> Vec<ButtonState> {
+        let mut state = NotPressed;
+        ticks
+            .iter()
+            .map(|events| {
+                events.iter().for_each(|&down| state.update(down));
+                let seen = state;
+                state.settle();
+                seen
+            })
+            .collect()
+    }
+
+    #[test]
+    fn edges_last_one_tick() {
+        assert_eq!(
+            run(&[&[true], &[], &[], &[false], &[], &[]]),
+            [Pressed, Held, Held, Released, NotPressed, NotPressed]
+        );
+    }
+
+    #[test]
+    fn repeats_while_held_stay_held() {
+        assert_eq!(run(&[&[true], &[true], &[true]]), [Pressed, Held, Held]);
+    }
+
+    #[test]
+    fn release_and_press_again_in_one_tick() {
+        assert_eq!(
+            run(&[&[true], &[false, true], &[]]),
+            [Pressed, Pressed, Held]
+        );
+    }
+
+    #[test]
+    fn tap_within_one_tick() {
+        assert_eq!(run(&[&[true, false], &[]]), [Released, NotPressed]);
+    }
+}
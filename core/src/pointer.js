@@ -0,0 +1,51 @@
+let wanted = false;
+let installed = false;
+let dx = 0;
+let dy = 0;
+
+function canvas() {
+    return document.querySelector("canvas");
+}
+
+function install() {
+    if (installed) {
+        return;
+    }
+    installed = true;
+    // browsers only grant the lock in response to a click, so keep asking until we get it
+    canvas().addEventListener("mousedown", () => {
+        if (wanted && !is_locked()) {
+            canvas().requestPointerLock();
+        }
+    });
+    document.addEventListener("mousemove", (e) => {
+        if (is_locked()) {
+            dx += e.movementX;
+            dy += e.movementY;
+        }
+    });
+}
+
+export function set_lock(lock) {
+    install();
+    if (lock === wanted) {
+        return;
+    }
+    wanted = lock;
+    if (lock) {
+        canvas().requestPointerLock();
+    } else if (is_locked()) {
+        document.exitPointerLock();
+    }
+}
+
+export function is_locked() {
+    return document.pointerLockElement === canvas();
+}
+
+export function drain() {
+    const motion = new Float64Array([dx, dy]);
+    dx = 0;
+    dy = 0;
+    return motion;
+}
@@ -0,0 +1,105 @@
+//! pointer lock, so mouse motion keeps being reported when the cursor would hit the edge of
+//! the window
+
+use crate::prelude::*;
+
+#[cfg(target_arch = "wasm32")]
+mod platform {
+    use super::*;
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen(module = "/src/pointer.js")]
+    extern "C" {
+        fn set_lock(lock: bool);
+        fn is_locked() -> bool;
+        fn drain() -> js_sys::Float64Array;
+    }
+
+    pub fn supported() -> bool {
+        true
+    }
+
+    pub fn apply(_win: &Window, lock: bool) {
+        set_lock(lock);
+    }
+
+    /// The browser may refuse the lock, or drop it when escape is pressed
+    pub fn locked() -> bool {
+        is_locked()
+    }
+
+    pub fn poll() -> Vector {
+        let motion = drain().to_vec();
+        Vector::new(motion[0] as f32, motion[1] as f32)
+    }
+
+    /// While locked the cursor stays put, so the motion only comes from `poll`
+    pub fn motion_from_events() -> bool {
+        !is_locked()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod platform {
+    use super::*;
+    use quicksilver::CursorIcon;
+    use std::cell::Cell;
+
+    thread_local! {
+        static HIDDEN: Cell<bool> = Cell::new(false);
+    }
+
+    // quicksilver's window has no way to grab the cursor, so the grab never succeeds; the best
+    // we can do is hide it and keep reporting motion while it's over the window
+    pub fn supported() -> bool {
+        false
+    }
+
+    pub fn apply(win: &Window, lock: bool) {
+        if HIDDEN.with(|h| h.replace(lock)) != lock {
+            win.set_cursor_icon(if lock {
+                None
+            } else {
+                Some(CursorIcon::Default)
+            });
+        }
+    }
+
+    /// Hiding the cursor doesn't stop it at the edges of the window, so it isn't a lock
+    pub fn locked() -> bool {
+        false
+    }
+
+    pub fn poll() -> Vector {
+        Vector::ZERO
+    }
+
+    pub fn motion_from_events() -> bool {
+        true
+    }
+}
+
+/// Whether the pointer can be locked at all on this platform
+pub fn supported() -> bool {
+    platform::supported()
+}
+
+/// Lock or unlock the pointer, doing nothing if it's already in that state
+pub fn apply(win: &Window, lock: bool) {
+    platform::apply(win, lock)
+}
+
+/// Whether the pointer is actually locked right now
+pub fn locked() -> bool {
+    platform::locked()
+}
+
+/// Take the motion of the locked pointer since the last call, in window pixels
+pub fn poll() -> Vector {
+    platform::poll()
+}
+
+/// Whether the motion of the pointer can be worked out from the positions in `PointerMoved`
+pub fn motion_from_events() -> bool {
+    platform::motion_from_events()
+}
@@ -1,7 +1,7 @@
 pub use std::cell::RefCell;
 
 pub use num_traits::ToPrimitive;
-pub use rustpython_vm::pyobject::{ItemProtocol, TypeProtocol};
+pub use rustpython_vm::pyobject::{IdProtocol, ItemProtocol, TypeProtocol};
 
 pub use crate::anim::Animation;
 pub use crate::resources::{ResourceConfig, Resources};
@@ -11,8 +11,8 @@ pub use futures::prelude::*;
 
 pub use quicksilver::{
     geom::{Circle, Rectangle, Shape, Transform, Vector},
-    graphics::{Color, FontRenderer, Graphics, Image, VectorFont},
-    input::{Event, Input, Key, MouseButton},
+    graphics::{Color, FontRenderer, Graphics, Image, PixelFormat, Surface, VectorFont},
+    input::{Event, GamepadAxis, GamepadButton, GamepadId, Input, Key, MouseButton},
     load_file, QuicksilverError, Result as QsResult, Window,
 };
 
@@ -38,3 +38,4 @@ scoped_thread_local!(pub static SPRITES: RefCell<Resources>);
 scoped_thread_local!(pub static RESOURCES: RefCell<ResourceConfig>);
 scoped_thread_local!(pub static GRAPHICS: RefCell<Graphics>);
 scoped_thread_local!(pub static STATE: RefCell<crate::State>);
+scoped_thread_local!(pub static WINDOW: Window);
@@ -1,9 +1,26 @@
 use crate::prelude::*;
 
+use crate::blend::Blend;
+use crate::shapes::{self, Cap, Contour, Join, Paint, StrokeStyle};
+use crate::tilemap::{self, Layer, LayerContent, ObjectShape, Properties, Property, Tilemap};
+use crate::ButtonState;
+use once_cell::sync::Lazy;
+use quicksilver::graphics::{Element, Mesh, Vertex};
+use rustpython_vm::builtins::{PyDict, PyStr};
 use rustpython_vm::function::FromArgs;
 use rustpython_vm::pyobject::PyIterable;
-
-pub(crate) use qs::make_module;
+use std::collections::HashMap;
+
+pub(crate) fn make_module(vm: &VirtualMachine) -> PyObjectRef {
+    let module = qs::make_module(vm);
+    extend_module!(vm, module, {
+        "PRESSED" => vm.ctx.new_str(ButtonState::Pressed.name()),
+        "HELD" => vm.ctx.new_str(ButtonState::Held.name()),
+        "RELEASED" => vm.ctx.new_str(ButtonState::Released.name()),
+        "NOT_PRESSED" => vm.ctx.new_str(ButtonState::NotPressed.name()),
+    });
+    module
+}
 
 macro_rules! extract_list {
     ($vm:expr, $obj:expr, $err:literal, $t:ty, $n:expr) => {
@@ -95,6 +112,135 @@ impl TryFromObject for PyColor {
     }
 }
 
+/// A color, or a gradient given as a dict like
+/// `{"linear": [[x0, y0], [x1, y1]], "stops": [[0, color], [1, color]]}` or
+/// `{"radial": [[x, y], radius], "stops": [[0, color], [1, color]]}`
+struct PyPaint(Paint);
+impl TryFromObject for PyPaint {
+    fn try_from_object(vm: &VirtualMachine, obj: PyObjectRef) -> PyResult<Self> {
+        let dict = match_class!(match obj {
+            d @ PyDict => d,
+            obj => return PyColor::try_from_object(vm, obj).map(|c| Self(Paint::Solid(c.0))),
+        });
+
+        let stops = dict
+            .get_item_option("stops", vm)?
+            .ok_or_else(|| vm.new_value_error("a gradient needs \"stops\"".to_owned()))?;
+        let mut stops = vm
+            .extract_elements::<PyObjectRef>(&stops)?
+            .into_iter()
+            .map(|stop| {
+                let (offset, color) = extract_list!(
+                    vm,
+                    stop,
+                    "expected [offset, color] for a gradient stop",
+                    ((a, PyNum), (b, PyColor))
+                )?;
+                Ok((offset.to_f32(), color.0))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        if stops.is_empty() {
+            return Err(vm.new_value_error("a gradient needs at least one stop".to_owned()));
+        }
+        stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        if let Some(linear) = dict.get_item_option("linear", vm)? {
+            let [start, end] = extract_list!(
+                vm,
+                linear,
+                "expected [start, end] for a linear gradient",
+                Point,
+                2
+            )?;
+            let (start, end) = (Vector::from(start), Vector::from(end));
+            if start == end {
+                return Err(vm.new_value_error(
+                    "a linear gradient needs different start and end points".to_owned(),
+                ));
+            }
+            Ok(Self(Paint::Linear { start, end, stops }))
+        } else if let Some(radial) = dict.get_item_option("radial", vm)? {
+            let (center, radius) = extract_list!(
+                vm,
+                radial,
+                "expected [center, radius] for a radial gradient",
+                ((a, Point), (b, PyNum))
+            )?;
+            if radius.0.is_nan() || radius.0 <= 0.0 {
+                return Err(
+                    vm.new_value_error("a radial gradient needs a positive radius".to_owned())
+                );
+            }
+            Ok(Self(Paint::Radial {
+                center: center.into(),
+                radius: radius.to_f32(),
+                stops,
+            }))
+        } else {
+            Err(vm.new_value_error("a gradient needs either \"linear\" or \"radial\"".to_owned()))
+        }
+    }
+}
+
+struct PyBlend(Blend);
+impl TryFromObject for PyBlend {
+    fn try_from_object(vm: &VirtualMachine, obj: PyObjectRef) -> PyResult<Self> {
+        let s = PyStrRef::try_from_object(vm, obj)?;
+        s.borrow_value()
+            .parse()
+            .map(Self)
+            .map_err(|e| vm.new_value_error(e))
+    }
+}
+
+struct PyJoin(Join);
+impl TryFromObject for PyJoin {
+    fn try_from_object(vm: &VirtualMachine, obj: PyObjectRef) -> PyResult<Self> {
+        let s = PyStrRef::try_from_object(vm, obj)?;
+        s.borrow_value()
+            .parse()
+            .map(Self)
+            .map_err(|e| vm.new_value_error(e))
+    }
+}
+
+struct PyCap(Cap);
+impl TryFromObject for PyCap {
+    fn try_from_object(vm: &VirtualMachine, obj: PyObjectRef) -> PyResult<Self> {
+        let s = PyStrRef::try_from_object(vm, obj)?;
+        s.borrow_value()
+            .parse()
+            .map(Self)
+            .map_err(|e| vm.new_value_error(e))
+    }
+}
+
+/// [dash, gap, dash, gap, ...]
+struct PyDash(Vec<f32>);
+impl TryFromObject for PyDash {
+    fn try_from_object(vm: &VirtualMachine, obj: PyObjectRef) -> PyResult<Self> {
+        let mut pattern: Vec<f32> = vm
+            .extract_elements::<PyNum>(&obj)?
+            .into_iter()
+            .map(PyNum::to_f32)
+            .collect();
+        if pattern.iter().any(|x| !x.is_finite() || *x <= 0.0) {
+            return Err(vm.new_value_error("dash pattern must be positive lengths".to_owned()));
+        }
+        if pattern.iter().sum::<f32>() < shapes::MIN_DASH_PATTERN {
+            return Err(vm.new_value_error(format!(
+                "dash pattern must add up to at least {} pixels",
+                shapes::MIN_DASH_PATTERN
+            )));
+        }
+        // like svg, an odd pattern is repeated so dashes and gaps keep alternating
+        if pattern.len() % 2 == 1 {
+            pattern = pattern.repeat(2);
+        }
+        Ok(Self(pattern))
+    }
+}
+
 struct PyNum(f64);
 impl TryFromObject for PyNum {
     fn try_from_object(vm: &VirtualMachine, obj: PyObjectRef) -> PyResult<Self> {
@@ -115,9 +261,120 @@ impl PyNum {
     }
 }
 
+/// A layer of a tilemap, by its name or index
+enum LayerRef {
+    Name(String),
+    Index(usize),
+}
+impl TryFromObject for LayerRef {
+    fn try_from_object(vm: &VirtualMachine, obj: PyObjectRef) -> PyResult<Self> {
+        // bools are ints to python, but True is never meant as layer 1
+        if obj.class().is(&vm.ctx.types.bool_type) {
+            return Err(vm.new_type_error("expected a layer name or index, not a bool".to_owned()));
+        }
+        match_class!(match obj {
+            s @ PyStr => Ok(LayerRef::Name(s.borrow_value().to_owned())),
+            i @ PyInt => i
+                .borrow_value()
+                .to_usize()
+                .map(LayerRef::Index)
+                .ok_or_else(|| vm.new_index_error("layer index out of range".to_owned())),
+            _ => Err(vm.new_type_error("expected a layer name or index".to_owned())),
+        })
+    }
+}
+
+/// One sprite drawn by `qs.sprites()`: [x, y], [x, y, rotation] or [x, y, rotation, scale]
+struct Instance {
+    pos: Vector,
+    rot: f32,
+    scale: f32,
+}
+impl TryFromObject for Instance {
+    fn try_from_object(vm: &VirtualMachine, obj: PyObjectRef) -> PyResult<Self> {
+        let v = vm.extract_elements::<PyNum>(&obj)?;
+        let (pos, rot, scale) = match v.as_slice() {
+            [x, y] => ((x.0, y.0), 0.0, 1.0),
+            [x, y, rot] => ((x.0, y.0), rot.0, 1.0),
+            [x, y, rot, scale] => ((x.0, y.0), rot.0, scale.0),
+            _ => {
+                return Err(vm
+                    .new_type_error("expected [x, y, rotation, scale] for each sprite".to_owned()))
+            }
+        };
+        Ok(Instance {
+            pos: Vector::new(pos.0 as f32, pos.1 as f32),
+            rot: rot as f32,
+            scale: scale as f32,
+        })
+    }
+}
+
+/// One rectangle drawn by `qs.rects()`, with an optional color of its own
+struct RectInstance(Rectangle, Option<Color>);
+impl TryFromObject for RectInstance {
+    fn try_from_object(vm: &VirtualMachine, obj: PyObjectRef) -> PyResult<Self> {
+        let v = vm.extract_elements::<PyObjectRef>(&obj)?;
+        let mut v = v.into_iter();
+        match (v.next(), v.next(), v.next(), v.next()) {
+            (Some(p0), Some(p1), color, None) => {
+                let (p0, size) = (
+                    Point::try_from_object(vm, p0)?,
+                    Point::try_from_object(vm, p1)?,
+                );
+                let color = match color {
+                    Some(color) => Some(PyColor::try_from_object(vm, color)?.0),
+                    None => None,
+                };
+                Ok(RectInstance(Rectangle::new(p0.into(), size.into()), color))
+            }
+            _ => Err(vm.new_type_error(
+                "expected [p0, size] or [p0, size, color] for each rectangle".to_owned(),
+            )),
+        }
+    }
+}
+
+/// The corners of a rectangle in the order top left, top right, bottom right, bottom left
+fn corners(rect: Rectangle) -> [Vector; 4] {
+    let (tl, br) = (rect.pos, rect.pos + rect.size);
+    [tl, Vector::new(br.x, tl.y), br, Vector::new(tl.x, br.y)]
+}
+
+/// The corners of the part of an image that's drawn, in texture coordinates
+fn uv_corners(image: &Image, src: Rectangle) -> [Vector; 4] {
+    let size = image.size();
+    corners(Rectangle::new(
+        Vector::new(src.pos.x / size.x, src.pos.y / size.y),
+        Vector::new(src.size.x / size.x, src.size.y / size.y),
+    ))
+}
+
+/// Add a quad to a mesh, with its corners in the same order as `corners()`
+fn push_quad(mesh: &mut Mesh, corners: [Vector; 4], uv: Option<[Vector; 4]>, color: Color) {
+    let start = mesh.vertices.len() as u32;
+    for (i, &pos) in corners.iter().enumerate() {
+        mesh.vertices.push(Vertex {
+            pos,
+            uv: uv.map(|uv| uv[i]),
+            color,
+        });
+    }
+    mesh.elements
+        .push(Element::Triangle([start, start + 1, start + 2]));
+    mesh.elements
+        .push(Element::Triangle([start + 2, start + 3, start]));
+}
+
+/// Count a draw call towards the stats shown by the debug overlay
+fn record_draw(vertices: usize) {
+    STATE.with(|s| s.borrow_mut().stats.record_draw(vertices))
+}
+
 #[pymodule]
 mod qs {
     use super::*;
+    use crate::actions::Binding;
 
     // INITIALIZATION FUNCTIONS
 
@@ -209,11 +466,140 @@ mod qs {
         })
     }
 
+    /// Load an image with many sprites packed into it. The data file is a TexturePacker JSON
+    /// (hash or array) file, or a grid like `{"cell": [16, 16], "names": [...]}`. Each region is
+    /// then drawn with `qs.sprite("name/region")`.
+    #[pyfunction]
+    fn init_atlas(name: PyStrRef, image: PyStrRef, data_file: PyStrRef) {
+        RESOURCES.with(|r| {
+            r.borrow_mut().atlases.push((
+                name.borrow_value().to_owned(),
+                image.borrow_value().to_owned(),
+                data_file.borrow_value().to_owned(),
+            ));
+        })
+    }
+
     // WINDOW FUNCTIONS
 
     #[pyfunction]
-    fn clear(PyColor(color): PyColor) {
-        GRAPHICS.with(|gfx| gfx.borrow_mut().clear(color))
+    fn clear(PyColor(color): PyColor, vm: &VirtualMachine) -> PyResult<()> {
+        GRAPHICS.with(|gfx| {
+            let mut gfx = gfx.borrow_mut();
+            let size = match target_size() {
+                Some(size) => size,
+                None => {
+                    gfx.clear(color);
+                    return Ok(());
+                }
+            };
+            // quicksilver can only clear the window, so cover the surface instead, replacing
+            // what's there rather than blending with it
+            flush_target(&mut gfx, vm)?;
+            gfx.set_transform(Transform::IDENTITY);
+            gfx.set_blend_mode(None);
+            gfx.fill_rect(&Rectangle::new_sized(size), color);
+            flush_target(&mut gfx, vm)?;
+            crate::blend::reset(&mut gfx);
+            STATE.with(|s| s.borrow_mut().blend = Blend::Normal);
+            Ok(())
+        })
+    }
+
+    // SURFACE FUNCTIONS
+
+    /// Make an image that can be drawn to with `draw_to`, and drawn like a sprite. Only works
+    /// once the game has loaded, in `onload` or later
+    #[pyfunction]
+    fn create_surface(
+        name: PyStrRef,
+        width: u32,
+        height: u32,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        if !GRAPHICS.is_set() {
+            return Err(vm.new_runtime_error(
+                "surfaces can only be created once the game has loaded, in onload or later"
+                    .to_owned(),
+            ));
+        }
+        GRAPHICS.with(|gfx| {
+            let gfx = gfx.borrow();
+            let surface = Image::from_raw(&gfx, None, width, height, PixelFormat::RGBA)
+                .map_err(QuicksilverError::from)
+                .and_then(|image| Ok((Surface::new(&gfx, image.clone())?, image)))
+                .map_err(|e| vm.new_runtime_error(e.to_string()))?;
+            SPRITES.with(|r| {
+                r.borrow_mut()
+                    .surfaces
+                    .insert(name.borrow_value().to_owned(), surface)
+            });
+            Ok(())
+        })
+    }
+
+    /// The size of the surface being drawn to, or None when drawing to the window
+    fn target_size() -> Option<Vector> {
+        let target = STATE.with(|s| s.borrow().target.clone())?;
+        SPRITES.with(|r| {
+            let r = r.borrow();
+            r.surfaces.get(&target).map(|(_, image)| image.size())
+        })
+    }
+
+    /// Send everything drawn so far to the surface or window it was meant for
+    fn flush_target(gfx: &mut Graphics, vm: &VirtualMachine) -> PyResult<()> {
+        let target = STATE.with(|s| s.borrow().target.clone());
+        let res = match target {
+            Some(name) => SPRITES.with(|r| match r.borrow().surfaces.get(&name) {
+                Some((surface, _)) => gfx.flush_surface(surface),
+                None => Ok(()),
+            }),
+            None if WINDOW.is_set() => WINDOW.with(|win| gfx.flush_window(win)),
+            None => {
+                return Err(vm.new_runtime_error(
+                    "surfaces can only be drawn to from the draw function".to_owned(),
+                ))
+            }
+        };
+        res.map_err(|e| vm.new_runtime_error(e.to_string()))
+    }
+
+    /// Send everything drawn from now on to a surface, until `draw_to_screen` is called. The
+    /// surface starts off without the view from `set_view`.
+    #[pyfunction]
+    fn draw_to(name: PyStrRef, vm: &VirtualMachine) -> PyResult<()> {
+        let name = name.borrow_value();
+        let size = SPRITES.with(|r| r.borrow().surfaces.get(name).map(|(_, image)| image.size()));
+        let size =
+            size.ok_or_else(|| vm.new_lookup_error(format!("surface {:?} does not exist", name)))?;
+        GRAPHICS.with(|gfx| {
+            let mut gfx = gfx.borrow_mut();
+            flush_target(&mut gfx, vm)?;
+            gfx.set_camera_size(size);
+            gfx.set_view(Transform::IDENTITY);
+            STATE.with(|s| {
+                let mut s = s.borrow_mut();
+                s.target = Some(name.to_owned());
+                s.target_view = Transform::IDENTITY;
+            });
+            Ok(())
+        })
+    }
+
+    #[pyfunction]
+    fn draw_to_screen(vm: &VirtualMachine) -> PyResult<()> {
+        GRAPHICS.with(|gfx| {
+            let mut gfx = gfx.borrow_mut();
+            flush_target(&mut gfx, vm)?;
+            STATE.with(|s| {
+                let mut s = s.borrow_mut();
+                s.target = None;
+                gfx.set_camera_size(s.camera_size);
+                gfx.set_view(s.view);
+            });
+            Ok(())
+        })
     }
 
     // TODO: namedtuple?
@@ -236,208 +622,1248 @@ mod qs {
         new_py_point(vm, v)
     }
 
+    /// How far the mouse moved during the last tick, which keeps working while the pointer is
+    /// locked
     #[pyfunction]
-    fn update_rate() -> f64 {
-        STATE.with(|s| s.borrow().update_rate)
+    fn mouse_delta(vm: &VirtualMachine) -> PyObjectRef {
+        let v = STATE.with(|s| s.borrow().mouse_delta);
+        new_py_point(vm, v)
     }
 
+    /// Hide the cursor and keep reporting mouse motion past the edges of the window. On the web,
+    /// the browser only grants the lock after the player clicks on the game. On desktop the
+    /// cursor can't be grabbed, so it's only hidden, `pointer_locked()` stays False and this
+    /// returns False to say the lock isn't possible
     #[pyfunction]
-    fn set_update_rate(PyNum(rate): PyNum) {
-        STATE.with(|s| s.borrow_mut().update_rate = rate)
+    fn set_pointer_lock(lock: bool) -> bool {
+        STATE.with(|s| s.borrow_mut().pointer_lock = lock);
+        crate::pointer::supported()
     }
 
+    /// Whether the pointer is actually locked, which is only the case once the lock is granted
     #[pyfunction]
-    fn keyboard(vm: &VirtualMachine) -> PyResult<PyDictRef> {
-        STATE.with(|state| {
-            let state = state.borrow();
-            let d = vm.ctx.new_dict();
-            for (key, state) in KEY_LIST.iter().zip(state.keyboard.iter()) {
-                let val = vm.ctx.new_str(format!("{:?}", state));
-                d.set_item(format!("{:?}", key), val, vm)?;
-            }
-            Ok(d)
-        })
+    fn pointer_locked() -> bool {
+        crate::pointer::locked()
     }
 
+    /// The text on the clipboard, or None if it can't be read right away. The browser never
+    /// lets us read it right away, so on the web this is always None; use `request_clipboard`
     #[pyfunction]
-    fn keyboard_bool(vm: &VirtualMachine) -> PyResult<PyDictRef> {
-        STATE.with(|state| {
-            let state = state.borrow();
-            let d = vm.ctx.new_dict();
-            for (key, state) in KEY_LIST.iter().zip(state.keyboard.iter()) {
-                let val = vm.ctx.new_bool(state.is_down());
-                d.set_item(format!("{:?}", key), val, vm)?;
-            }
-            Ok(d)
-        })
+    fn get_clipboard() -> Option<String> {
+        crate::clipboard::get()
     }
 
+    /// Start reading the clipboard. The text arrives later as a `clipboard` event, once the
+    /// browser lets us read it on the web, or next frame on desktop
     #[pyfunction]
-    fn set_view(PyRect(rect): PyRect) {
-        let winsize = STATE.with(|s| s.borrow().winsize);
-
-        let trans = Transform::translate(-rect.pos).then(Transform::scale(Vector::new(
-            rect.size.x / winsize.x,
-            rect.size.y / winsize.y,
-        )));
-
-        GRAPHICS.with(|gfx| gfx.borrow_mut().set_view(trans))
+    fn request_clipboard() {
+        crate::clipboard::request()
     }
 
-    // SHAPE FUNCTIONS
-
-    #[derive(FromArgs)]
-    struct ShapeArgs {
-        #[pyarg(named, optional)]
-        transform: PyTransform,
-        #[pyarg(named, default = "PyColor(Color::RED)")]
-        color: PyColor,
+    #[pyfunction]
+    fn set_clipboard(text: PyStrRef, vm: &VirtualMachine) -> PyResult<()> {
+        crate::clipboard::set(text.borrow_value())
+            .map_err(|e| vm.new_runtime_error(format!("couldn't set the clipboard: {}", e)))
     }
-    impl ShapeArgs {
-        fn into_drawable(self) -> (Color, Transform) {
-            (self.color.0, self.transform.0)
-        }
+
+    /// Start collecting typed text into an editable line, which starts out holding `text`
+    #[pyfunction]
+    fn start_text_input(text: OptionalArg<PyStrRef>) {
+        let text = text.as_ref().map_or("", |t| t.borrow_value());
+        STATE.with(|s| s.borrow_mut().text_input = Some(crate::text_input::TextInput::new(text)));
+        crate::text_input::set_active(true);
     }
 
-    fn shape_transform(trans: Transform, center: Vector) -> Transform {
-        Transform::translate(-center)
-            .then(trans)
-            .then(Transform::translate(center))
+    #[pyfunction]
+    fn stop_text_input() {
+        STATE.with(|s| s.borrow_mut().text_input = None);
+        crate::text_input::set_active(false);
     }
 
+    /// The text entered since `start_text_input`, or None if text input isn't active
     #[pyfunction]
-    fn rect(PyRect(rect): PyRect, args: ShapeArgs) {
-        let (color, trans) = args.into_drawable();
-        GRAPHICS.with(|gfx| {
-            let mut gfx = gfx.borrow_mut();
+    fn text_input_value() -> Option<String> {
+        STATE.with(|s| s.borrow().text_input.as_ref().map(|t| t.value()))
+    }
 
-            let trans = shape_transform(trans, rect.center());
-            gfx.set_transform(trans);
-            gfx.fill_rect(&rect, color);
-        })
+    /// The index of the character the cursor is in front of
+    #[pyfunction]
+    fn text_input_cursor() -> Option<usize> {
+        STATE.with(|s| s.borrow().text_input.as_ref().map(|t| t.cursor()))
     }
 
+    /// The (start, end) character range that is selected, or None if nothing is
     #[pyfunction]
-    fn circ(center: Point, radius: PyNum, args: ShapeArgs) {
-        let (color, trans) = args.into_drawable();
-        let circle = Circle::new(center.into(), radius.to_f32());
-        GRAPHICS.with(|gfx| {
-            let mut gfx = gfx.borrow_mut();
-            let trans = shape_transform(trans, circle.center());
-            gfx.set_transform(trans);
-            gfx.fill_circle(&circle, color);
-        })
+    fn text_input_selection(vm: &VirtualMachine) -> PyObjectRef {
+        let sel = STATE.with(|s| s.borrow().text_input.as_ref().and_then(|t| t.selection()));
+        match sel {
+            Some((start, end)) => vm
+                .ctx
+                .new_tuple(vec![vm.ctx.new_int(start), vm.ctx.new_int(end)]),
+            None => vm.ctx.none(),
+        }
     }
 
+    /// Text the IME is still composing, which should be drawn at the cursor
     #[pyfunction]
-    fn triangle(tri: PyTriangle, args: ShapeArgs) {
-        let (color, trans) = args.into_drawable();
-        GRAPHICS.with(|gfx| {
-            let mut gfx = gfx.borrow_mut();
-            let center = (tri.0[0] + tri.0[1] + tri.0[2]) / 3.0;
-            let trans = shape_transform(trans, center);
-            gfx.set_transform(trans);
-            gfx.fill_polygon(&tri.0, color);
+    fn text_input_composition() -> String {
+        STATE.with(|s| {
+            s.borrow()
+                .text_input
+                .as_ref()
+                .map_or(String::new(), |t| t.composition.clone())
         })
     }
 
-    #[derive(FromArgs)]
-    struct LineArgs {
-        #[pyarg(named, default = "PyNum(1.0)")]
-        thickness: PyNum,
-        #[pyarg(flatten)]
-        common: ShapeArgs,
+    fn mouse_state(name: &str, vm: &VirtualMachine) -> PyResult<ButtonState> {
+        let i = crate::actions::parse_mouse_button(name)
+            .ok_or_else(|| vm.new_value_error(format!("unknown mouse button {:?}", name)))?;
+        Ok(STATE.with(|s| s.borrow().mouse[i]))
     }
 
+    /// The state of "Left", "Middle" or "Right", as one of the button state constants
     #[pyfunction]
-    fn line(PyRect(rect): PyRect, args: LineArgs) {
-        use std::f32::consts;
-        GRAPHICS.with(|gfx| {
-            let mut gfx = gfx.borrow_mut();
-
-            let (color, trans) = args.common.into_drawable();
-            let thickness = args.thickness.to_f32();
-
-            let start = rect.pos;
-            let end = rect.size;
-
-            gfx.set_transform(trans);
-
-            if thickness == 1.0 {
-                gfx.stroke_path(&[start, end], color);
-                return;
-            }
-
-            // because we have a 'thickness' arg, we need to turn it into a tilted rectangle
+    fn mouse_button(name: PyStrRef, vm: &VirtualMachine) -> PyResult<&'static str> {
+        Ok(mouse_state(name.borrow_value(), vm)?.name())
+    }
 
-            let line = end - start;
-            let angle = line.y.atan2(line.x);
+    #[pyfunction]
+    fn mouse_down(name: PyStrRef, vm: &VirtualMachine) -> PyResult<bool> {
+        Ok(mouse_state(name.borrow_value(), vm)?.is_down())
+    }
 
-            let half_width = thickness / 2.0;
-            let angle_unit = |angle: f32| Vector::new(angle.cos(), angle.sin());
-            let right = angle_unit(angle - consts::FRAC_PI_2) * half_width;
-            let left = angle_unit(angle + consts::FRAC_PI_2) * half_width;
+    #[pyfunction]
+    fn mouse_pressed(name: PyStrRef, vm: &VirtualMachine) -> PyResult<bool> {
+        Ok(mouse_state(name.borrow_value(), vm)? == ButtonState::Pressed)
+    }
 
-            let points = [start + right, end + right, end + left, start + left];
+    #[pyfunction]
+    fn mouse_released(name: PyStrRef, vm: &VirtualMachine) -> PyResult<bool> {
+        Ok(mouse_state(name.borrow_value(), vm)? == ButtonState::Released)
+    }
 
-            gfx.fill_polygon(&points, color);
+    /// The touch points currently on the screen, including ones lifted this frame
+    #[pyfunction]
+    fn touches(vm: &VirtualMachine) -> PyResult<PyObjectRef> {
+        STATE.with(|s| {
+            let s = s.borrow();
+            let touches = s
+                .touches
+                .iter()
+                .map(|t| {
+                    let d = vm.ctx.new_namespace();
+                    vm.set_attr(&d, "id", vm.ctx.new_int(t.id))?;
+                    vm.set_attr(&d, "x", vm.ctx.new_float(t.pos.x.into()))?;
+                    vm.set_attr(&d, "y", vm.ctx.new_float(t.pos.y.into()))?;
+                    vm.set_attr(&d, "phase", vm.ctx.new_str(t.phase.name()))?;
+                    Ok(d)
+                })
+                .collect::<PyResult<_>>()?;
+            Ok(vm.ctx.new_list(touches))
         })
     }
 
-    // DRAW FUNCTIONS
-
+    /// Make the first finger on the screen act as the left mouse button. When it's off, the
+    /// browser's own emulation still turns taps into clicks
     #[pyfunction]
-    fn sound(s: PyStrRef, vm: &VirtualMachine) -> PyResult<()> {
-        SPRITES.with(|r| {
-            let resources = r.borrow();
-            let sound = resources.get_sound(s.borrow_value()).ok_or_else(|| {
-                vm.new_lookup_error(format!("sound {:?} does not exist", s.borrow_value()))
-            })?;
-            sound
-                .play()
-                .map_err(|e| vm.new_runtime_error(e.to_string()))
-        })
+    fn set_touch_mouse_emulation(enabled: bool) {
+        STATE.with(|s| s.borrow_mut().emulate_mouse = enabled)
     }
 
-    enum RectOrPoint {
-        Rect(Rectangle),
-        Point(Point),
+    #[pyfunction]
+    fn update_rate() -> f64 {
+        STATE.with(|s| s.borrow().update_rate)
     }
-    impl RectOrPoint {
-        fn to_rect(self, size: Vector) -> Rectangle {
-            match self {
-                RectOrPoint::Rect(rect) => rect,
-                RectOrPoint::Point(p0) => Rectangle::new(p0.into(), size),
-            }
+
+    /// Set the time between update ticks, in milliseconds
+    #[pyfunction]
+    fn set_update_rate(PyNum(rate): PyNum, vm: &VirtualMachine) -> PyResult<()> {
+        if !rate.is_finite() || rate <= 0.0 {
+            return Err(vm.new_value_error(format!(
+                "update rate must be a positive number of milliseconds, not {}",
+                rate
+            )));
         }
+        STATE.with(|s| s.borrow_mut().update_rate = rate);
+        Ok(())
     }
-    impl FromArgs for RectOrPoint {
-        fn from_args(
-            vm: &VirtualMachine,
-            args: &mut FuncArgs,
-        ) -> std::result::Result<Self, rustpython_vm::function::ArgumentError> {
-            match (args.take_keyword("rect"), args.take_keyword("p0")) {
-                (Some(rect), None) => Ok(Self::Rect(PyRect::try_from_object(vm, rect)?.0)),
-                (None, Some(point)) => Ok(Self::Point(Point::try_from_object(vm, point)?)),
-                (None, None) => Err("sprite() must have either `p0=` or `rect=` named argument"),
-                (Some(_), Some(_)) => {
-                    Err("sprite() must have either `p0=` or `rect=` named argument, but not both")
-                }
+
+    #[pyfunction]
+    fn set_timestep(mode: PyStrRef, vm: &VirtualMachine) -> PyResult<()> {
+        let timestep = match mode.borrow_value() {
+            "fixed" => crate::Timestep::Fixed,
+            "variable" => crate::Timestep::Variable,
+            other => {
+                return Err(vm.new_value_error(format!(
+                    "unknown timestep {:?}, expected \"fixed\" or \"variable\"",
+                    other
+                )))
             }
-            .map_err(|s| vm.new_type_error(s.to_owned()).into())
-        }
+        };
+        STATE.with(|s| s.borrow_mut().timestep = timestep);
+        Ok(())
+    }
 
-        fn arity() -> std::ops::RangeInclusive<usize> {
-            0..=1
+    #[pyfunction]
+    fn set_max_catch_up(steps: u32, vm: &VirtualMachine) -> PyResult<()> {
+        if steps == 0 {
+            return Err(vm.new_value_error("max_catch_up must be at least 1".to_owned()));
         }
+        STATE.with(|s| s.borrow_mut().max_catch_up = steps);
+        Ok(())
     }
 
-    #[derive(FromArgs)]
-    struct SpriteArgs {
-        #[pyarg(flatten)]
-        position: RectOrPoint,
-        #[pyarg(named, optional)]
-        transform: PyTransform,
+    #[pyfunction]
+    fn frame_alpha() -> f64 {
+        STATE.with(|s| s.borrow().frame_alpha)
+    }
+
+    // TIME FUNCTIONS
+
+    /// Game time since the first update, in seconds
+    #[pyfunction]
+    fn time() -> f64 {
+        STATE.with(|s| s.borrow().time)
+    }
+
+    /// The length of the current update tick, in seconds
+    #[pyfunction]
+    fn delta() -> f64 {
+        STATE.with(|s| s.borrow().delta / 1000.0)
+    }
+
+    #[pyfunction]
+    fn tick_count() -> u64 {
+        STATE.with(|s| s.borrow().tick_count)
+    }
+
+    #[pyfunction]
+    fn frame_count() -> u64 {
+        STATE.with(|s| s.borrow().frame_count)
+    }
+
+    #[pyfunction]
+    fn time_scale() -> f64 {
+        STATE.with(|s| s.borrow().time_scale)
+    }
+
+    #[pyfunction]
+    fn set_time_scale(PyNum(scale): PyNum, vm: &VirtualMachine) -> PyResult<()> {
+        if scale.is_nan() || scale < 0.0 {
+            return Err(vm.new_value_error("time scale must be a non-negative number".to_owned()));
+        }
+        STATE.with(|s| s.borrow_mut().time_scale = scale);
+        Ok(())
+    }
+
+    #[pyfunction]
+    fn keyboard(vm: &VirtualMachine) -> PyResult<PyDictRef> {
+        STATE.with(|state| {
+            let state = state.borrow();
+            let d = vm.ctx.new_dict();
+            for (name, state) in KEY_NAMES.iter().zip(state.keyboard.iter()) {
+                d.set_item(name.as_str(), vm.ctx.new_str(state.name()), vm)?;
+            }
+            Ok(d)
+        })
+    }
+
+    #[pyfunction]
+    fn keyboard_bool(vm: &VirtualMachine) -> PyResult<PyDictRef> {
+        STATE.with(|state| {
+            let state = state.borrow();
+            let d = vm.ctx.new_dict();
+            for (name, state) in KEY_NAMES.iter().zip(state.keyboard.iter()) {
+                d.set_item(name.as_str(), vm.ctx.new_bool(state.is_down()), vm)?;
+            }
+            Ok(d)
+        })
+    }
+
+    fn key_state(name: &str, vm: &VirtualMachine) -> PyResult<ButtonState> {
+        let i =
+            key_index(name).ok_or_else(|| vm.new_value_error(format!("unknown key {:?}", name)))?;
+        Ok(STATE.with(|s| s.borrow().keyboard[i]))
+    }
+
+    /// The state of a key, as one of the `PRESSED`, `HELD`, `RELEASED` or `NOT_PRESSED` constants
+    #[pyfunction(name = "key_state")]
+    fn py_key_state(name: PyStrRef, vm: &VirtualMachine) -> PyResult<&'static str> {
+        Ok(key_state(name.borrow_value(), vm)?.name())
+    }
+
+    #[pyfunction]
+    fn key_down(name: PyStrRef, vm: &VirtualMachine) -> PyResult<bool> {
+        Ok(key_state(name.borrow_value(), vm)?.is_down())
+    }
+
+    #[pyfunction]
+    fn key_pressed(name: PyStrRef, vm: &VirtualMachine) -> PyResult<bool> {
+        Ok(key_state(name.borrow_value(), vm)? == ButtonState::Pressed)
+    }
+
+    #[pyfunction]
+    fn key_released(name: PyStrRef, vm: &VirtualMachine) -> PyResult<bool> {
+        Ok(key_state(name.borrow_value(), vm)? == ButtonState::Released)
+    }
+
+    /// The names of every key that is down
+    #[pyfunction]
+    fn keys_down(vm: &VirtualMachine) -> PyObjectRef {
+        let down: Vec<_> = STATE.with(|s| {
+            s.borrow()
+                .keyboard
+                .iter()
+                .zip(KEY_NAMES.iter())
+                .filter(|(state, _)| state.is_down())
+                .map(|(_, name)| vm.ctx.new_str(name.as_str()))
+                .collect()
+        });
+        vm.ctx.new_list(down)
+    }
+
+    #[pyfunction]
+    fn modifiers(vm: &VirtualMachine) -> PyResult<PyObjectRef> {
+        let mods = STATE.with(|s| s.borrow().modifiers());
+        let d = vm.ctx.new_namespace();
+        vm.set_attr(&d, "shift", vm.ctx.new_bool(mods.shift))?;
+        vm.set_attr(&d, "ctrl", vm.ctx.new_bool(mods.ctrl))?;
+        vm.set_attr(&d, "alt", vm.ctx.new_bool(mods.alt))?;
+        vm.set_attr(&d, "super", vm.ctx.new_bool(mods.logo))?;
+        Ok(d)
+    }
+
+    /// Set the layout of the player's keyboard, used to tell where keys physically are on desktop
+    ///
+    /// On the web the browser reports where keys are itself, so the layout is ignored there.
+    #[pyfunction]
+    fn set_keyboard_layout(name: PyStrRef, vm: &VirtualMachine) -> PyResult<()> {
+        let layout = name
+            .borrow_value()
+            .parse()
+            .map_err(|e| vm.new_value_error(e))?;
+        STATE.with(|s| s.borrow_mut().keyboard_layout = layout);
+        Ok(())
+    }
+
+    /// The key the player has at the position of `name` on a US QWERTY keyboard, e.g. "Z" for
+    /// "W" on an AZERTY keyboard
+    #[pyfunction]
+    fn logical_key(name: PyStrRef, vm: &VirtualMachine) -> PyResult<&'static str> {
+        let key = parse_key(name.borrow_value())
+            .ok_or_else(|| vm.new_value_error(format!("unknown key {:?}", name.borrow_value())))?;
+        let layout = STATE.with(|s| s.borrow().keyboard_layout);
+        Ok(key_name(layout.to_logical(key)))
+    }
+
+    // ACTION FUNCTIONS
+
+    fn parse_bindings(inputs: PyIterable<PyStrRef>, vm: &VirtualMachine) -> PyResult<Vec<Binding>> {
+        inputs
+            .iter(vm)?
+            .map(|input| {
+                input?
+                    .borrow_value()
+                    .parse()
+                    .map_err(|e| vm.new_value_error(e))
+            })
+            .collect()
+    }
+
+    /// Bind an action to a list of inputs, replacing whatever it was bound to before
+    #[pyfunction]
+    fn bind(name: PyStrRef, inputs: PyIterable<PyStrRef>, vm: &VirtualMachine) -> PyResult<()> {
+        let bindings = parse_bindings(inputs, vm)?;
+        STATE.with(|s| {
+            s.borrow_mut()
+                .actions
+                .insert(name.borrow_value().to_owned(), bindings)
+        });
+        Ok(())
+    }
+
+    #[pyfunction]
+    fn unbind(name: PyStrRef) {
+        STATE.with(|s| s.borrow_mut().actions.remove(name.borrow_value()));
+    }
+
+    /// Every action's bindings as a dict of lists of strings, ready to be saved
+    #[pyfunction]
+    fn bindings(vm: &VirtualMachine) -> PyResult<PyDictRef> {
+        STATE.with(|s| {
+            let d = vm.ctx.new_dict();
+            for (name, bindings) in &s.borrow().actions {
+                let inputs = bindings
+                    .iter()
+                    .map(|b| vm.ctx.new_str(b.to_string()))
+                    .collect();
+                d.set_item(name.as_str(), vm.ctx.new_list(inputs), vm)?;
+            }
+            Ok(d)
+        })
+    }
+
+    /// Bind every action in a dict like the one returned by `bindings()`
+    #[pyfunction]
+    fn load_bindings(bindings: PyDictRef, vm: &VirtualMachine) -> PyResult<()> {
+        for (name, inputs) in bindings {
+            let name = PyStrRef::try_from_object(vm, name)?;
+            let inputs = parse_bindings(PyIterable::try_from_object(vm, inputs)?, vm)?;
+            STATE.with(|s| {
+                s.borrow_mut()
+                    .actions
+                    .insert(name.borrow_value().to_owned(), inputs)
+            });
+        }
+        Ok(())
+    }
+
+    fn action_state(name: &str, vm: &VirtualMachine) -> PyResult<crate::ButtonState> {
+        STATE.with(|s| {
+            let s = s.borrow();
+            let bindings = s
+                .actions
+                .get(name)
+                .ok_or_else(|| vm.new_lookup_error(format!("action {:?} is not bound", name)))?;
+            Ok(crate::actions::action_state(bindings, &s))
+        })
+    }
+
+    #[pyfunction]
+    fn action_pressed(name: PyStrRef, vm: &VirtualMachine) -> PyResult<bool> {
+        Ok(action_state(name.borrow_value(), vm)? == ButtonState::Pressed)
+    }
+
+    #[pyfunction]
+    fn action_held(name: PyStrRef, vm: &VirtualMachine) -> PyResult<bool> {
+        Ok(action_state(name.borrow_value(), vm)?.is_down())
+    }
+
+    #[pyfunction]
+    fn action_released(name: PyStrRef, vm: &VirtualMachine) -> PyResult<bool> {
+        Ok(action_state(name.borrow_value(), vm)? == ButtonState::Released)
+    }
+
+    #[derive(FromArgs)]
+    struct AxisArgs {
+        #[pyarg(named, optional)]
+        neg: OptionalArg<PyStrRef>,
+        #[pyarg(named, optional)]
+        pos: OptionalArg<PyStrRef>,
+    }
+
+    /// A value from -1 to 1 combining any analog inputs bound to `name` with the `neg` and `pos`
+    /// actions, which push the value to -1 and 1 respectively
+    #[pyfunction]
+    fn axis(name: PyStrRef, args: AxisArgs, vm: &VirtualMachine) -> PyResult<f32> {
+        let held = |action: OptionalArg<PyStrRef>| -> PyResult<f32> {
+            match action.into_option() {
+                Some(action) if action_state(action.borrow_value(), vm)?.is_down() => Ok(1.0),
+                _ => Ok(0.0),
+            }
+        };
+        let digital = held(args.pos)? - held(args.neg)?;
+        let analog = STATE.with(|s| {
+            let s = s.borrow();
+            s.actions.get(name.borrow_value()).map_or(0.0, |bindings| {
+                bindings
+                    .iter()
+                    .filter(|b| b.is_analog())
+                    .map(|b| b.value(&s))
+                    .sum()
+            })
+        });
+        Ok((digital + analog).max(-1.0).min(1.0))
+    }
+
+    // GAMEPAD FUNCTIONS
+
+    /// The indices of the currently connected gamepads
+    #[pyfunction]
+    fn gamepads(vm: &VirtualMachine) -> PyObjectRef {
+        let connected: Vec<_> = STATE.with(|s| {
+            let s = s.borrow();
+            s.gamepads
+                .iter()
+                .enumerate()
+                .filter(|(_, pad)| pad.connected)
+                .map(|(i, _)| vm.ctx.new_int(i))
+                .collect()
+        });
+        vm.ctx.new_list(connected)
+    }
+
+    #[pyfunction]
+    fn gamepad_button(idx: usize, name: PyStrRef, vm: &VirtualMachine) -> PyResult<&'static str> {
+        let button = crate::gamepad::parse_button(name.borrow_value()).ok_or_else(|| {
+            vm.new_value_error(format!("unknown gamepad button {:?}", name.borrow_value()))
+        })?;
+        let state = STATE.with(|s| {
+            s.borrow()
+                .gamepads
+                .get(idx)
+                .map_or(ButtonState::NotPressed, |pad| pad.button(button))
+        });
+        Ok(state.name())
+    }
+
+    #[pyfunction]
+    fn gamepad_axis(idx: usize, name: PyStrRef, vm: &VirtualMachine) -> PyResult<f32> {
+        let axis = crate::gamepad::parse_axis(name.borrow_value()).ok_or_else(|| {
+            vm.new_value_error(format!("unknown gamepad axis {:?}", name.borrow_value()))
+        })?;
+        Ok(STATE.with(|s| {
+            s.borrow()
+                .gamepads
+                .get(idx)
+                .map_or(0.0, |pad| pad.axis(axis))
+        }))
+    }
+
+    /// Set the deadzone of one gamepad, or of every gamepad if no index is given
+    #[pyfunction]
+    fn set_gamepad_deadzone(
+        PyNum(deadzone): PyNum,
+        idx: OptionalArg<usize>,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        if !(0.0..1.0).contains(&deadzone) {
+            return Err(vm.new_value_error("deadzone must be between 0 and 1".to_owned()));
+        }
+        let deadzone = deadzone as f32;
+        STATE.with(|s| {
+            let mut s = s.borrow_mut();
+            match idx.into_option() {
+                Some(idx) => {
+                    let pad = s.gamepads.get_mut(idx).ok_or_else(|| {
+                        vm.new_index_error(format!("no gamepad with index {}", idx))
+                    })?;
+                    pad.deadzone = deadzone;
+                }
+                None => {
+                    s.gamepad_deadzone = deadzone;
+                    for pad in &mut s.gamepads {
+                        pad.deadzone = deadzone;
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    #[pyfunction]
+    fn set_view(PyRect(rect): PyRect) {
+        let winsize = STATE.with(|s| s.borrow().winsize);
+
+        let trans = Transform::translate(-rect.pos).then(Transform::scale(Vector::new(
+            rect.size.x / winsize.x,
+            rect.size.y / winsize.y,
+        )));
+
+        // a surface's view only lasts until the next draw_to or draw_to_screen
+        STATE.with(|s| {
+            let mut s = s.borrow_mut();
+            match s.target {
+                Some(_) => s.target_view = trans,
+                None => s.view = trans,
+            }
+        });
+        GRAPHICS.with(|gfx| gfx.borrow_mut().set_view(trans))
+    }
+
+    #[pyfunction]
+    fn debug_overlay(enabled: bool) {
+        STATE.with(|s| s.borrow_mut().debug_overlay = enabled)
+    }
+
+    #[pyfunction]
+    fn set_pause_on_blur(enabled: bool) {
+        STATE.with(|s| s.borrow_mut().pause_on_blur = enabled)
+    }
+
+    /// Ask the game to exit after this frame's updates, unless `on_quit` returns `False`
+    #[pyfunction]
+    fn quit(code: OptionalArg<i32>) {
+        STATE.with(|s| s.borrow_mut().exit_code = Some(code.unwrap_or(0)))
+    }
+
+    // BLENDING FUNCTIONS
+
+    /// Fade everything drawn until the matching `pop_alpha` by `alpha`, on top of any fading
+    /// that's already been pushed
+    #[pyfunction]
+    fn push_alpha(alpha: PyNum) {
+        let alpha = alpha.to_f32().max(0.0).min(1.0);
+        STATE.with(|s| s.borrow_mut().alpha_stack.push(alpha))
+    }
+
+    #[pyfunction]
+    fn pop_alpha(vm: &VirtualMachine) -> PyResult<()> {
+        STATE
+            .with(|s| s.borrow_mut().alpha_stack.pop())
+            .map(drop)
+            .ok_or_else(|| vm.new_index_error("pop_alpha without a matching push_alpha".to_owned()))
+    }
+
+    /// Set up the transform and blend mode for a draw call
+    fn begin_draw(
+        gfx: &mut Graphics,
+        trans: Transform,
+        blend: Blend,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        gfx.set_transform(trans);
+        let (current, target) = STATE.with(|s| {
+            let s = s.borrow();
+            (s.blend, s.target.clone())
+        });
+        if current == blend {
+            return Ok(());
+        }
+        // changing the blend mode flushes to the window, so anything meant for a surface has
+        // to be sent there first
+        if target.is_some() {
+            flush_target(gfx, vm)?;
+        }
+        STATE.with(|s| crate::blend::apply(gfx, blend, &mut s.borrow_mut().blend));
+        Ok(())
+    }
+
+    /// Apply the fading from `push_alpha` to a color
+    fn fade(color: Color) -> Color {
+        let alpha = STATE.with(|s| s.borrow().alpha());
+        Color {
+            a: color.a * alpha,
+            ..color
+        }
+    }
+
+    // SHAPE FUNCTIONS
+
+    #[derive(FromArgs)]
+    struct StrokeArgs {
+        #[pyarg(named, default = "PyNum(1.0)")]
+        thickness: PyNum,
+        #[pyarg(named, default = "PyJoin(Join::Miter)")]
+        join: PyJoin,
+        #[pyarg(named, default = "PyCap(Cap::Butt)")]
+        cap: PyCap,
+        #[pyarg(named, optional)]
+        dash: OptionalArg<PyDash>,
+        #[pyarg(named, default = "PyNum(0.0)")]
+        dash_offset: PyNum,
+    }
+    impl StrokeArgs {
+        fn style(&self) -> StrokeStyle {
+            StrokeStyle {
+                thickness: (self.thickness.0 as f32).max(0.0),
+                join: self.join.0,
+                cap: self.cap.0,
+                dash: self.dash.as_option().map_or(Vec::new(), |d| d.0.clone()),
+                dash_offset: self.dash_offset.0 as f32,
+            }
+        }
+
+        /// Whether the line can be drawn as a plain rectangle without tessellating it
+        fn is_simple(&self) -> bool {
+            self.cap.0 == Cap::Butt && self.dash.as_option().is_none()
+        }
+    }
+
+    #[derive(FromArgs)]
+    struct ShapeArgs {
+        #[pyarg(named, optional)]
+        transform: PyTransform,
+        #[pyarg(named, default = "PyPaint(Paint::Solid(Color::RED))")]
+        color: PyPaint,
+        /// Set to False to only draw the outline
+        #[pyarg(named, default = "true")]
+        fill: bool,
+        #[pyarg(named, optional)]
+        outline: OptionalArg<PyPaint>,
+        #[pyarg(flatten)]
+        stroke: StrokeArgs,
+        #[pyarg(named, default = "PyBlend(Blend::Normal)")]
+        blend: PyBlend,
+    }
+
+    #[derive(FromArgs)]
+    struct PolygonArgs {
+        /// A color for each point, blended across the inside instead of using `color`
+        #[pyarg(named, optional)]
+        colors: OptionalArg<PyIterable<PyColor>>,
+        #[pyarg(flatten)]
+        shape: ShapeArgs,
+    }
+    impl PolygonArgs {
+        /// The shape arguments, with the color replaced by the per-point colors if there are any
+        fn into_shape(self, points: &[Vector], vm: &VirtualMachine) -> PyResult<ShapeArgs> {
+            let mut shape = self.shape;
+            if let OptionalArg::Present(colors) = self.colors {
+                let colors = colors
+                    .iter(vm)?
+                    .map(|c| c.map(|c| c.0))
+                    .collect::<PyResult<Vec<_>>>()?;
+                if colors.len() != points.len() {
+                    return Err(vm.new_value_error(format!(
+                        "expected {} colors, one for each point",
+                        points.len()
+                    )));
+                }
+                shape.color = PyPaint(Paint::Vertices(
+                    points.iter().copied().zip(colors).collect(),
+                ));
+            }
+            Ok(shape)
+        }
+    }
+
+    fn draw_mesh(
+        gfx: &mut Graphics,
+        mesh: Result<Mesh, String>,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        let mut mesh = mesh.map_err(|e| vm.new_value_error(e))?;
+        for v in &mut mesh.vertices {
+            v.color = fade(v.color);
+        }
+        gfx.draw_mesh(&mesh);
+        record_draw(mesh.vertices.len());
+        Ok(())
+    }
+
+    /// Outline a closed shape, if `outline=` was given
+    fn draw_outline(
+        gfx: &mut Graphics,
+        points: Vec<Vector>,
+        args: &ShapeArgs,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        match args.outline.as_option() {
+            Some(paint) => {
+                let mesh =
+                    shapes::stroke(&[Contour::closed(points)], &args.stroke.style(), &paint.0);
+                draw_mesh(gfx, mesh, vm)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Fill a convex shape, letting quicksilver do it when there's no gradient
+    fn fill_convex(
+        gfx: &mut Graphics,
+        points: &[Vector],
+        paint: &Paint,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        match paint {
+            Paint::Solid(color) => {
+                gfx.fill_polygon(points, fade(*color));
+                record_draw(points.len());
+                Ok(())
+            }
+            paint => {
+                let mesh = shapes::fill(&[Contour::closed(points.to_vec())], paint);
+                draw_mesh(gfx, mesh, vm)
+            }
+        }
+    }
+
+    fn shape_transform(trans: Transform, center: Vector) -> Transform {
+        Transform::translate(-center)
+            .then(trans)
+            .then(Transform::translate(center))
+    }
+
+    #[pyfunction]
+    fn rect(PyRect(rect): PyRect, args: ShapeArgs, vm: &VirtualMachine) -> PyResult<()> {
+        GRAPHICS.with(|gfx| {
+            let mut gfx = gfx.borrow_mut();
+
+            let trans = shape_transform(args.transform.0, rect.center());
+            begin_draw(&mut gfx, trans, args.blend.0, vm)?;
+            let (tl, br) = (rect.pos, rect.pos + rect.size);
+            let corners = vec![tl, Vector::new(br.x, tl.y), br, Vector::new(tl.x, br.y)];
+            if args.fill {
+                fill_convex(&mut gfx, &corners, &args.color.0, vm)?;
+            }
+            draw_outline(&mut gfx, corners, &args, vm)
+        })
+    }
+
+    #[derive(FromArgs)]
+    struct RectsArgs {
+        #[pyarg(named, default = "PyColor(Color::RED)")]
+        color: PyColor,
+        #[pyarg(named, default = "PyBlend(Blend::Normal)")]
+        blend: PyBlend,
+    }
+
+    /// Fill many rectangles at once, each given as [p0, size] or [p0, size, color]
+    #[pyfunction]
+    fn rects(
+        rects: PyIterable<RectInstance>,
+        args: RectsArgs,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        let mut mesh = Mesh {
+            vertices: Vec::new(),
+            elements: Vec::new(),
+            image: None,
+        };
+        for rect in rects.iter(vm)? {
+            let RectInstance(rect, color) = rect?;
+            let color = fade(color.unwrap_or(args.color.0));
+            push_quad(&mut mesh, corners(rect), None, color);
+        }
+        GRAPHICS.with(|gfx| {
+            let mut gfx = gfx.borrow_mut();
+            begin_draw(&mut gfx, Transform::IDENTITY, args.blend.0, vm)?;
+            gfx.draw_mesh(&mesh);
+            record_draw(mesh.vertices.len());
+            Ok(())
+        })
+    }
+
+    #[pyfunction]
+    fn circ(center: Point, radius: PyNum, args: ShapeArgs, vm: &VirtualMachine) -> PyResult<()> {
+        let circle = Circle::new(center.into(), radius.to_f32());
+        GRAPHICS.with(|gfx| {
+            let mut gfx = gfx.borrow_mut();
+            let trans = shape_transform(args.transform.0, circle.center());
+            begin_draw(&mut gfx, trans, args.blend.0, vm)?;
+            // the fill and outline share their points so they line up
+            let points = shapes::ellipse(circle.pos, Vector::new(circle.radius, circle.radius));
+            if args.fill {
+                fill_convex(&mut gfx, &points, &args.color.0, vm)?;
+            }
+            draw_outline(&mut gfx, points, &args, vm)
+        })
+    }
+
+    #[pyfunction]
+    fn triangle(tri: PyTriangle, args: PolygonArgs, vm: &VirtualMachine) -> PyResult<()> {
+        let args = args.into_shape(&tri.0, vm)?;
+        GRAPHICS.with(|gfx| {
+            let mut gfx = gfx.borrow_mut();
+            let center = (tri.0[0] + tri.0[1] + tri.0[2]) / 3.0;
+            let trans = shape_transform(args.transform.0, center);
+            begin_draw(&mut gfx, trans, args.blend.0, vm)?;
+            if args.fill {
+                fill_convex(&mut gfx, &tri.0, &args.color.0, vm)?;
+            }
+            draw_outline(&mut gfx, tri.0.to_vec(), &args, vm)
+        })
+    }
+
+    /// Fill and outline any closed shape, transforming it around `center`
+    fn draw_shape(
+        points: Vec<Vector>,
+        center: Vector,
+        args: &ShapeArgs,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        GRAPHICS.with(|gfx| {
+            let mut gfx = gfx.borrow_mut();
+            begin_draw(
+                &mut gfx,
+                shape_transform(args.transform.0, center),
+                args.blend.0,
+                vm,
+            )?;
+            let contour = Contour::closed(points);
+            if args.fill {
+                let mesh = shapes::fill(std::slice::from_ref(&contour), &args.color.0);
+                draw_mesh(&mut gfx, mesh, vm)?;
+            }
+            draw_outline(&mut gfx, contour.points, args, vm)
+        })
+    }
+
+    /// Draw a line through `points` in `color`, transforming it around `center`
+    fn draw_line(
+        contour: Contour,
+        center: Vector,
+        args: &ShapeArgs,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        GRAPHICS.with(|gfx| {
+            let mut gfx = gfx.borrow_mut();
+            begin_draw(
+                &mut gfx,
+                shape_transform(args.transform.0, center),
+                args.blend.0,
+                vm,
+            )?;
+            let mesh = shapes::stroke(&[contour], &args.stroke.style(), &args.color.0);
+            draw_mesh(&mut gfx, mesh, vm)
+        })
+    }
+
+    fn extract_points(
+        points: PyIterable<Point>,
+        min: usize,
+        vm: &VirtualMachine,
+    ) -> PyResult<Vec<Vector>> {
+        let points = points
+            .iter(vm)?
+            .map(|p| p.map(Vector::from))
+            .collect::<PyResult<Vec<_>>>()?;
+        if points.len() < min {
+            return Err(vm.new_value_error(format!("expected at least {} points", min)));
+        }
+        Ok(points)
+    }
+
+    fn centroid(points: &[Vector]) -> Vector {
+        points.iter().fold(Vector::ZERO, |sum, p| sum + *p) / points.len() as f32
+    }
+
+    /// A closed shape through `points`, which may be concave
+    #[pyfunction]
+    fn polygon(points: PyIterable<Point>, args: PolygonArgs, vm: &VirtualMachine) -> PyResult<()> {
+        let points = extract_points(points, 3, vm)?;
+        let args = args.into_shape(&points, vm)?;
+        let center = centroid(&points);
+        draw_shape(points, center, &args, vm)
+    }
+
+    /// A line through `points` in `color`
+    #[pyfunction]
+    fn polyline(points: PyIterable<Point>, args: ShapeArgs, vm: &VirtualMachine) -> PyResult<()> {
+        let points = extract_points(points, 2, vm)?;
+        let center = centroid(&points);
+        draw_line(Contour::open(points), center, &args, vm)
+    }
+
+    #[pyfunction]
+    fn ellipse(center: Point, radii: Point, args: ShapeArgs, vm: &VirtualMachine) -> PyResult<()> {
+        let center = Vector::from(center);
+        draw_shape(shapes::ellipse(center, radii.into()), center, &args, vm)
+    }
+
+    /// A curved line around `center`, with the angles in degrees clockwise from the right
+    #[pyfunction]
+    fn arc(
+        center: Point,
+        radius: PyNum,
+        start: PyNum,
+        end: PyNum,
+        args: ShapeArgs,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        let center = Vector::from(center);
+        let radii = Vector::new(radius.0 as f32, radius.0 as f32);
+        let points = shapes::arc(center, radii, start.to_f32(), end.to_f32());
+        draw_line(Contour::open(points), center, &args, vm)
+    }
+
+    /// A slice of a circle, with the angles in degrees clockwise from the right
+    #[pyfunction]
+    fn pie(
+        center: Point,
+        radius: PyNum,
+        start: PyNum,
+        end: PyNum,
+        args: ShapeArgs,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        let center = Vector::from(center);
+        let radii = Vector::new(radius.0 as f32, radius.0 as f32);
+        let mut points = vec![center];
+        points.extend(shapes::arc(center, radii, start.to_f32(), end.to_f32()));
+        draw_shape(points, center, &args, vm)
+    }
+
+    #[pyfunction]
+    fn rounded_rect(
+        PyRect(rect): PyRect,
+        radius: PyNum,
+        args: ShapeArgs,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        let points = shapes::rounded_rect(&rect, radius.to_f32());
+        draw_shape(points, rect.center(), &args, vm)
+    }
+
+    // PATH FUNCTIONS
+
+    fn with_path(f: impl FnOnce(&mut shapes::PathBuilder)) {
+        STATE.with(|s| f(&mut s.borrow_mut().path))
+    }
+
+    /// Throw away the current path and start a new one
+    #[pyfunction]
+    fn begin_path() {
+        with_path(|path| *path = shapes::PathBuilder::default())
+    }
+
+    #[pyfunction]
+    fn move_to(p: Point) {
+        with_path(|path| path.move_to(p.into()))
+    }
+
+    #[pyfunction]
+    fn line_to(p: Point) {
+        with_path(|path| path.line_to(p.into()))
+    }
+
+    #[pyfunction]
+    fn quad_to(ctrl: Point, p: Point) {
+        with_path(|path| path.quad_to(ctrl.into(), p.into()))
+    }
+
+    #[pyfunction]
+    fn cubic_to(ctrl1: Point, ctrl2: Point, p: Point) {
+        with_path(|path| path.cubic_to(ctrl1.into(), ctrl2.into(), p.into()))
+    }
+
+    /// Continue the path along an arc around `center`, with the angles in degrees clockwise
+    /// from the right, like `arc` on an html canvas
+    #[pyfunction]
+    fn path_arc(center: Point, radius: PyNum, start: PyNum, end: PyNum) {
+        with_path(|path| path.arc(center.into(), radius.to_f32(), start.to_f32(), end.to_f32()))
+    }
+
+    /// Round off the corner at `p1` with an arc of `radius` that touches the line from the end
+    /// of the path to `p1` and the line from `p1` to `p2`, like `arcTo` on an html canvas
+    #[pyfunction]
+    fn arc_to(p1: Point, p2: Point, radius: PyNum, vm: &VirtualMachine) -> PyResult<()> {
+        let radius = radius.to_f32();
+        if radius < 0.0 || radius.is_nan() {
+            return Err(vm.new_value_error(format!("radius must be 0 or more, not {}", radius)));
+        }
+        with_path(|path| path.arc_to(p1.into(), p2.into(), radius));
+        Ok(())
+    }
+
+    #[pyfunction]
+    fn close_path() {
+        with_path(|path| path.close())
+    }
+
+    fn path_contours() -> Vec<Contour> {
+        STATE.with(|s| s.borrow().path.contours())
+    }
+
+    fn contours_center(contours: &[Contour]) -> Vector {
+        let points: Vec<Vector> = contours.iter().flat_map(|c| c.points.clone()).collect();
+        if points.is_empty() {
+            Vector::ZERO
+        } else {
+            centroid(&points)
+        }
+    }
+
+    /// Fill the inside of the current path, closing any open pieces, and outline it if
+    /// `outline=` is given
+    #[pyfunction]
+    fn fill_path(args: ShapeArgs, vm: &VirtualMachine) -> PyResult<()> {
+        let contours = path_contours();
+        GRAPHICS.with(|gfx| {
+            let mut gfx = gfx.borrow_mut();
+            begin_draw(
+                &mut gfx,
+                shape_transform(args.transform.0, contours_center(&contours)),
+                args.blend.0,
+                vm,
+            )?;
+            if args.fill {
+                draw_mesh(&mut gfx, shapes::fill(&contours, &args.color.0), vm)?;
+            }
+            if let Some(paint) = args.outline.as_option() {
+                let mesh = shapes::stroke(&contours, &args.stroke.style(), &paint.0);
+                draw_mesh(&mut gfx, mesh, vm)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Draw a line along the current path in `color`
+    #[pyfunction]
+    fn stroke_path(args: ShapeArgs, vm: &VirtualMachine) -> PyResult<()> {
+        let contours = path_contours();
+        GRAPHICS.with(|gfx| {
+            let mut gfx = gfx.borrow_mut();
+            begin_draw(
+                &mut gfx,
+                shape_transform(args.transform.0, contours_center(&contours)),
+                args.blend.0,
+                vm,
+            )?;
+            let mesh = shapes::stroke(&contours, &args.stroke.style(), &args.color.0);
+            draw_mesh(&mut gfx, mesh, vm)
+        })
+    }
+
+    /// A curve from the first point to the last, using the 1 or 2 points in between as control
+    /// points
+    #[pyfunction]
+    fn bezier(points: PyIterable<Point>, args: ShapeArgs, vm: &VirtualMachine) -> PyResult<()> {
+        let p = extract_points(points, 3, vm)?;
+        let mut curve = vec![p[0]];
+        match p.len() {
+            3 => curve.extend(shapes::quad(p[0], p[1], p[2])),
+            4 => curve.extend(shapes::cubic(p[0], p[1], p[2], p[3])),
+            _ => return Err(vm.new_value_error("expected 3 or 4 points for a bezier".to_owned())),
+        }
+        let center = centroid(&p);
+        draw_line(Contour::open(curve), center, &args, vm)
+    }
+
+    /// Draw a line from `rect[0]` to `rect[1]` in `color`, with the stroke options of the other
+    /// shapes
+    #[pyfunction]
+    fn line(PyRect(rect): PyRect, args: ShapeArgs, vm: &VirtualMachine) -> PyResult<()> {
+        use std::f32::consts;
+        GRAPHICS.with(|gfx| {
+            let mut gfx = gfx.borrow_mut();
+
+            let thickness = args.stroke.thickness.0 as f32;
+
+            let start = rect.pos;
+            let end = rect.size;
+
+            begin_draw(&mut gfx, args.transform.0, args.blend.0, vm)?;
+
+            let color = match args.color.0 {
+                Paint::Solid(color) if args.stroke.is_simple() => fade(color),
+                _ => {
+                    let line = Contour::open(vec![start, end]);
+                    let mesh = shapes::stroke(&[line], &args.stroke.style(), &args.color.0);
+                    return draw_mesh(&mut gfx, mesh, vm);
+                }
+            };
+
+            if thickness == 1.0 {
+                gfx.stroke_path(&[start, end], color);
+                record_draw(2);
+                return Ok(());
+            }
+
+            // because we have a 'thickness' arg, we need to turn it into a tilted rectangle
+
+            let line = end - start;
+            let angle = line.y.atan2(line.x);
+
+            let half_width = thickness / 2.0;
+            let angle_unit = |angle: f32| Vector::new(angle.cos(), angle.sin());
+            let right = angle_unit(angle - consts::FRAC_PI_2) * half_width;
+            let left = angle_unit(angle + consts::FRAC_PI_2) * half_width;
+
+            let points = [start + right, end + right, end + left, start + left];
+
+            gfx.fill_polygon(&points, color);
+            record_draw(4);
+            Ok(())
+        })
+    }
+
+    // DRAW FUNCTIONS
+
+    #[pyfunction]
+    fn sound(s: PyStrRef, vm: &VirtualMachine) -> PyResult<()> {
+        SPRITES.with(|r| {
+            let resources = r.borrow();
+            let sound = resources.get_sound(s.borrow_value()).ok_or_else(|| {
+                vm.new_lookup_error(format!("sound {:?} does not exist", s.borrow_value()))
+            })?;
+            sound
+                .play()
+                .map_err(|e| vm.new_runtime_error(e.to_string()))
+        })
+    }
+
+    enum RectOrPoint {
+        Rect(Rectangle),
+        Point(Point),
+    }
+    impl RectOrPoint {
+        fn to_rect(self, size: Vector) -> Rectangle {
+            match self {
+                RectOrPoint::Rect(rect) => rect,
+                RectOrPoint::Point(p0) => Rectangle::new(p0.into(), size),
+            }
+        }
+    }
+    impl FromArgs for RectOrPoint {
+        fn from_args(
+            vm: &VirtualMachine,
+            args: &mut FuncArgs,
+        ) -> std::result::Result<Self, rustpython_vm::function::ArgumentError> {
+            match (args.take_keyword("rect"), args.take_keyword("p0")) {
+                (Some(rect), None) => Ok(Self::Rect(PyRect::try_from_object(vm, rect)?.0)),
+                (None, Some(point)) => Ok(Self::Point(Point::try_from_object(vm, point)?)),
+                (None, None) => Err("sprite() must have either `p0=` or `rect=` named argument"),
+                (Some(_), Some(_)) => {
+                    Err("sprite() must have either `p0=` or `rect=` named argument, but not both")
+                }
+            }
+            .map_err(|s| vm.new_type_error(s.to_owned()).into())
+        }
+
+        fn arity() -> std::ops::RangeInclusive<usize> {
+            0..=1
+        }
+    }
+
+    /// Options shared by `sprite` and `anim`
+    #[derive(FromArgs)]
+    struct ImageArgs {
+        #[pyarg(named, default = "false")]
+        flip_x: bool,
+        #[pyarg(named, default = "false")]
+        flip_y: bool,
+        /// Multiplied with each pixel of the image
+        #[pyarg(named, default = "PyColor(Color::WHITE)")]
+        tint: PyColor,
+        #[pyarg(named, default = "PyNum(1.0)")]
+        alpha: PyNum,
+        /// The point the transform rotates and scales around, as a fraction of the size, so
+        /// [0, 0] is the top left and [0.5, 1] is the middle of the bottom edge
+        #[pyarg(named, default = "Point(0.5, 0.5)")]
+        origin: Point,
+        /// The part of the image to draw, measured from its top left corner
+        #[pyarg(named, optional)]
+        src: OptionalArg<PyRect>,
+    }
+
+    impl ImageArgs {
+        fn src(&self) -> Option<Rectangle> {
+            self.src.as_option().map(|r| r.0)
+        }
+
+        fn color(&self) -> Color {
+            let tint = self.tint.0;
+            fade(Color {
+                a: tint.a * self.alpha.0 as f32,
+                ..tint
+            })
+        }
+
+        /// Flip the image in place, then apply `trans` around the origin
+        fn transform(&self, trans: Transform, location: Rectangle) -> Transform {
+            let flip = Vector::new(
+                if self.flip_x { -1.0 } else { 1.0 },
+                if self.flip_y { -1.0 } else { 1.0 },
+            );
+            let Point(ox, oy) = self.origin;
+            let origin = location.pos + Vector::new(location.size.x * ox, location.size.y * oy);
+            shape_transform(Transform::scale(flip), location.center())
+                .then(shape_transform(trans, origin))
+        }
+    }
+
+    #[derive(FromArgs)]
+    struct SpriteArgs {
+        #[pyarg(flatten)]
+        position: RectOrPoint,
+        #[pyarg(named, optional)]
+        transform: PyTransform,
+        #[pyarg(named, default = "PyBlend(Blend::Normal)")]
+        blend: PyBlend,
+        #[pyarg(flatten)]
+        image: ImageArgs,
     }
 
     #[pyfunction]
@@ -448,13 +1874,77 @@ mod qs {
                 let resources = r.borrow();
                 let mut gfx = gfx.borrow_mut();
 
-                let im = resources.get_img(&name).ok_or_else(|| {
+                let (im, mut region) = resources.get_sprite(name).ok_or_else(|| {
+                    vm.new_lookup_error(format!("sprite {:?} does not exist", name))
+                })?;
+                if let Some(src) = args.image.src() {
+                    region = region.part(src);
+                }
+
+                let location = args.position.to_rect(region.size);
+                let trans = args.image.transform(args.transform.0, location);
+                begin_draw(&mut gfx, trans, args.blend.0, vm)?;
+                let dest = region.place(location);
+                gfx.draw_subimage_tinted(im, region.src, dest, args.image.color());
+                record_draw(4);
+
+                Ok(())
+            })
+        })
+    }
+
+    #[derive(FromArgs)]
+    struct SpritesArgs {
+        #[pyarg(named, default = "PyBlend(Blend::Normal)")]
+        blend: PyBlend,
+        #[pyarg(flatten)]
+        image: ImageArgs,
+    }
+
+    /// Draw a sprite many times at once. Each instance is [x, y], [x, y, rotation] or
+    /// [x, y, rotation, scale], with x, y at the top left, rotating and scaling around `origin`.
+    #[pyfunction]
+    fn sprites(
+        name: PyStrRef,
+        instances: PyIterable<Instance>,
+        args: SpritesArgs,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        let name = name.borrow_value();
+        // iterating can run python code, like a generator, which may want the resources too
+        let instances = instances.iter(vm)?.collect::<PyResult<Vec<_>>>()?;
+        GRAPHICS.with(|gfx| {
+            SPRITES.with(|r| {
+                let resources = r.borrow();
+                let mut gfx = gfx.borrow_mut();
+
+                let (im, mut region) = resources.get_sprite(name).ok_or_else(|| {
                     vm.new_lookup_error(format!("sprite {:?} does not exist", name))
                 })?;
+                if let Some(src) = args.image.src() {
+                    region = region.part(src);
+                }
+
+                let uv = uv_corners(im, region.src);
+                let color = args.image.color();
+                let mut mesh = Mesh {
+                    vertices: Vec::new(),
+                    elements: Vec::new(),
+                    image: Some(im.clone()),
+                };
+                for Instance { pos, rot, scale } in instances {
+                    let location = Rectangle::new(pos, region.size);
+                    let trans =
+                        Transform::rotate(rot).then(Transform::scale(Vector::new(scale, scale)));
+                    let trans = args.image.transform(trans, location);
+                    let [tl, tr, br, bl] = corners(region.place(location));
+                    let corners = [trans * tl, trans * tr, trans * br, trans * bl];
+                    push_quad(&mut mesh, corners, Some(uv), color);
+                }
 
-                gfx.set_transform(args.transform.0);
-                let location = args.position.to_rect(im.size());
-                gfx.draw_image(im, location);
+                begin_draw(&mut gfx, Transform::IDENTITY, args.blend.0, vm)?;
+                gfx.draw_mesh(&mesh);
+                record_draw(mesh.vertices.len());
 
                 Ok(())
             })
@@ -469,6 +1959,8 @@ mod qs {
         color: PyColor,
         #[pyarg(named, optional)]
         font: OptionalArg<PyStrRef>,
+        #[pyarg(named, default = "PyBlend(Blend::Normal)")]
+        blend: PyBlend,
     }
 
     #[pyfunction]
@@ -478,22 +1970,26 @@ mod qs {
             .as_option()
             .map_or("default", |s| s.borrow_value());
         let text = text.borrow_value();
-        let TextArgs { p0, color, .. } = args;
+        let TextArgs {
+            p0, color, blend, ..
+        } = args;
 
         GRAPHICS.with(|gfx| {
             SPRITES.with(|r| {
-                let mut resources = r.borrow_mut();
                 let mut gfx = gfx.borrow_mut();
+                // before borrowing the fonts, since flushing to a surface needs the resources
+                begin_draw(&mut gfx, Transform::IDENTITY, blend.0, vm)?;
+                let mut resources = r.borrow_mut();
                 let (font, pt) = resources.get_font(font_name).ok_or_else(|| {
                     vm.new_lookup_error(format!("font {:?} does not exist", font_name))
                 })?;
 
-                gfx.set_transform(Transform::IDENTITY);
                 let mut offset = Vector::from(p0);
                 // font.draw renders at the lower right corner, for some reason.
                 offset.y += pt;
-                font.draw(&mut gfx, text, color.0, offset)
+                font.draw(&mut gfx, text, fade(color.0), offset)
                     .map_err(|e| vm.new_runtime_error(e.to_string()))?;
+                record_draw(4 * text.chars().filter(|c| !c.is_whitespace()).count());
 
                 Ok(())
             })
@@ -506,6 +2002,10 @@ mod qs {
         position: RectOrPoint,
         #[pyarg(named, optional)]
         transform: PyTransform,
+        #[pyarg(named, default = "PyBlend(Blend::Normal)")]
+        blend: PyBlend,
+        #[pyarg(flatten)]
+        image: ImageArgs,
     }
 
     #[pyfunction]
@@ -520,12 +2020,15 @@ mod qs {
                     vm.new_lookup_error(format!("animation {:?} does not exist", name))
                 })?;
 
-                let location = args.position.to_rect(anim.frame_size);
+                let src = args.image.src();
+                let size = src.map_or(anim.frame_size, |src| src.size);
+                let location = args.position.to_rect(size);
 
-                let trans = shape_transform(args.transform.0, location.center());
-                gfx.set_transform(trans);
+                let trans = args.image.transform(args.transform.0, location);
+                begin_draw(&mut gfx, trans, args.blend.0, vm)?;
 
-                anim.draw(&mut gfx, location);
+                anim.draw(&mut gfx, src, location, args.image.color());
+                record_draw(4);
 
                 Ok(())
             })
@@ -548,6 +2051,375 @@ mod qs {
             Ok(())
         })
     }
+
+    // TILEMAP FUNCTIONS
+
+    fn get_tilemap<'a>(
+        resources: &'a Resources,
+        name: &str,
+        vm: &VirtualMachine,
+    ) -> PyResult<&'a Tilemap> {
+        resources
+            .get_tilemap(name)
+            .ok_or_else(|| vm.new_lookup_error(format!("tilemap {:?} does not exist", name)))
+    }
+
+    fn get_layer<'a>(
+        map: &'a Tilemap,
+        layer: &LayerRef,
+        vm: &VirtualMachine,
+    ) -> PyResult<&'a Layer> {
+        match layer {
+            LayerRef::Name(name) => map
+                .layers
+                .iter()
+                .find(|l| &l.name == name)
+                .ok_or_else(|| vm.new_lookup_error(format!("layer {:?} does not exist", name))),
+            LayerRef::Index(i) => map
+                .layers
+                .get(*i)
+                .ok_or_else(|| vm.new_index_error(format!("layer {} does not exist", i))),
+        }
+    }
+
+    fn tiles<'a>(layer: &'a Layer, vm: &VirtualMachine) -> PyResult<&'a [u32]> {
+        match &layer.content {
+            LayerContent::Tiles(tiles) => Ok(tiles),
+            LayerContent::Objects(_) => Err(vm.new_type_error(format!(
+                "layer {:?} is an object layer, not a tile layer",
+                layer.name
+            ))),
+        }
+    }
+
+    /// The part of the world that can be seen through the current view
+    fn visible_area() -> Rectangle {
+        let size = target_size();
+        STATE.with(|s| {
+            let s = s.borrow();
+            let (view, size) = match size {
+                Some(size) => (s.target_view, size),
+                None => (s.view, s.camera_size),
+            };
+            let inverse = view.inverse();
+            let points = corners(Rectangle::new_sized(size));
+            let (mut min, mut max) = (inverse * points[0], inverse * points[0]);
+            for &p in &points[1..] {
+                let p = inverse * p;
+                min = Vector::new(min.x.min(p.x), min.y.min(p.y));
+                max = Vector::new(max.x.max(p.x), max.y.max(p.y));
+            }
+            Rectangle::new(min, max - min)
+        })
+    }
+
+    fn property_to_py(p: &Property, vm: &VirtualMachine) -> PyObjectRef {
+        match p {
+            Property::Bool(b) => vm.ctx.new_bool(*b),
+            Property::Int(i) => vm.ctx.new_int(*i),
+            Property::Float(f) => vm.ctx.new_float(*f),
+            Property::String(s) => vm.ctx.new_str(s.clone()),
+        }
+    }
+
+    fn properties_to_py(props: &Properties, vm: &VirtualMachine) -> PyResult<PyDictRef> {
+        let d = vm.ctx.new_dict();
+        for (name, p) in props {
+            d.set_item(name.as_str(), property_to_py(p, vm), vm)?;
+        }
+        Ok(d)
+    }
+
+    fn new_py_rect(vm: &VirtualMachine, rect: Rectangle) -> PyObjectRef {
+        vm.ctx.new_list(vec![
+            new_py_point(vm, rect.pos),
+            new_py_point(vm, rect.size),
+        ])
+    }
+
+    #[pyfunction]
+    fn init_tilemaps(maps: PyIterable, vm: &VirtualMachine) -> PyResult<()> {
+        let it = maps.iter(vm)?.map(|el| -> PyResult<_> {
+            let [name, path] = extract_list!(
+                vm,
+                el?,
+                "expected [name, path] for init_tilemaps",
+                PyStrRef,
+                2
+            )?;
+            Ok((
+                name.borrow_value().to_owned(),
+                path.borrow_value().to_owned(),
+            ))
+        });
+        itertools::process_results(it, |it| {
+            RESOURCES.with(|r| {
+                r.borrow_mut().tilemaps.extend(it);
+            })
+        })
+    }
+
+    #[derive(FromArgs)]
+    struct TilemapArgs {
+        #[pyarg(named, default = "PyBlend(Blend::Normal)")]
+        blend: PyBlend,
+    }
+
+    /// Draw the tiles of a layer that can be seen, with the map's top left corner at 0, 0
+    ///
+    /// Layers hidden in Tiled aren't drawn when given by index, so drawing every layer in a loop
+    /// only shows the visible ones. Give a hidden layer by name to draw it anyway.
+    #[pyfunction]
+    fn draw_tilemap(
+        name: PyStrRef,
+        layer: LayerRef,
+        args: TilemapArgs,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        let area = visible_area();
+        GRAPHICS.with(|gfx| {
+            SPRITES.with(|r| {
+                let resources = r.borrow();
+                let mut gfx = gfx.borrow_mut();
+
+                let map = get_tilemap(&resources, name.borrow_value(), vm)?;
+                let by_name = matches!(layer, LayerRef::Name(_));
+                let layer = get_layer(map, &layer, vm)?;
+                let tiles = tiles(layer, vm)?;
+                if !layer.visible && !by_name {
+                    return Ok(());
+                }
+
+                let color = fade(Color {
+                    a: layer.opacity,
+                    ..Color::WHITE
+                });
+                // one mesh per tileset, since each mesh can only have one image
+                let mut meshes: Vec<Mesh> = map
+                    .tilesets
+                    .iter()
+                    .map(|t| Mesh {
+                        vertices: Vec::new(),
+                        elements: Vec::new(),
+                        image: Some(t.image.clone()),
+                    })
+                    .collect();
+                let (cols, rows) = map.visible(layer, area);
+                for row in rows {
+                    for col in cols.clone() {
+                        let gid = tiles[(row * map.width + col) as usize];
+                        let (i, tileset) = match map.tileset(gid) {
+                            Some(t) => t,
+                            None => continue,
+                        };
+                        let id = tilemap::strip_flags(gid) - tileset.first_gid;
+                        let mut uv = uv_corners(&tileset.image, tileset.region(id));
+                        // flipped the same way Tiled does it: diagonally, then x, then y
+                        if gid & tilemap::FLIP_DIAGONAL != 0 {
+                            uv.swap(1, 3);
+                        }
+                        if gid & tilemap::FLIP_X != 0 {
+                            uv.swap(0, 1);
+                            uv.swap(2, 3);
+                        }
+                        if gid & tilemap::FLIP_Y != 0 {
+                            uv.swap(0, 3);
+                            uv.swap(1, 2);
+                        }
+                        // tiles are lined up with the bottom left of their cell
+                        let pos = layer.offset
+                            + Vector::new(
+                                col as f32 * map.tile_size.x,
+                                (row + 1) as f32 * map.tile_size.y - tileset.tile_size.y,
+                            );
+                        let dest = Rectangle::new(pos, tileset.tile_size);
+                        push_quad(&mut meshes[i], corners(dest), Some(uv), color);
+                    }
+                }
+
+                begin_draw(&mut gfx, Transform::IDENTITY, args.blend.0, vm)?;
+                for mesh in meshes.iter().filter(|m| !m.vertices.is_empty()) {
+                    gfx.draw_mesh(mesh);
+                    record_draw(mesh.vertices.len());
+                }
+
+                Ok(())
+            })
+        })
+    }
+
+    /// The tile at a position in the world, or None if there isn't one
+    #[pyfunction]
+    fn tile_at(
+        name: PyStrRef,
+        layer: LayerRef,
+        x: PyNum,
+        y: PyNum,
+        vm: &VirtualMachine,
+    ) -> PyResult<PyObjectRef> {
+        SPRITES.with(|r| {
+            let resources = r.borrow();
+            let map = get_tilemap(&resources, name.borrow_value(), vm)?;
+            let layer = get_layer(map, &layer, vm)?;
+            let tiles = tiles(layer, vm)?;
+
+            let pos = Vector::new(x.to_f32(), y.to_f32());
+            let (col, row) = match map.tile_pos(layer, pos) {
+                Some(pos) => pos,
+                None => return Ok(vm.ctx.none()),
+            };
+            let gid = tiles[(row * map.width + col) as usize];
+            let tileset = match map.tileset(gid) {
+                Some((_, t)) => t,
+                None => return Ok(vm.ctx.none()),
+            };
+            let id = tilemap::strip_flags(gid) - tileset.first_gid;
+            let d = vm.ctx.new_namespace();
+            vm.set_attr(&d, "gid", vm.ctx.new_int(tilemap::strip_flags(gid)))?;
+            vm.set_attr(&d, "id", vm.ctx.new_int(id))?;
+            vm.set_attr(&d, "tileset", vm.ctx.new_str(tileset.name.clone()))?;
+            vm.set_attr(&d, "column", vm.ctx.new_int(col))?;
+            vm.set_attr(&d, "row", vm.ctx.new_int(row))?;
+            let props = match tileset.tile_properties.get(&id) {
+                Some(props) => properties_to_py(props, vm)?,
+                None => vm.ctx.new_dict(),
+            };
+            vm.set_attr(&d, "properties", props)?;
+            Ok(d)
+        })
+    }
+
+    /// The size, layers and custom properties of a tilemap
+    #[pyfunction]
+    fn tilemap_info(name: PyStrRef, vm: &VirtualMachine) -> PyResult<PyObjectRef> {
+        SPRITES.with(|r| {
+            let resources = r.borrow();
+            let map = get_tilemap(&resources, name.borrow_value(), vm)?;
+
+            let layers = map
+                .layers
+                .iter()
+                .map(|l| {
+                    let d = vm.ctx.new_namespace();
+                    let kind = match l.content {
+                        LayerContent::Tiles(_) => "tiles",
+                        LayerContent::Objects(_) => "objects",
+                    };
+                    vm.set_attr(&d, "name", vm.ctx.new_str(l.name.clone()))?;
+                    vm.set_attr(&d, "kind", vm.ctx.new_str(kind))?;
+                    vm.set_attr(&d, "visible", vm.ctx.new_bool(l.visible))?;
+                    vm.set_attr(&d, "opacity", vm.ctx.new_float(l.opacity.into()))?;
+                    vm.set_attr(&d, "offset", new_py_point(vm, l.offset))?;
+                    vm.set_attr(&d, "properties", properties_to_py(&l.properties, vm)?)?;
+                    Ok(d)
+                })
+                .collect::<PyResult<_>>()?;
+
+            let d = vm.ctx.new_namespace();
+            vm.set_attr(&d, "width", vm.ctx.new_int(map.width))?;
+            vm.set_attr(&d, "height", vm.ctx.new_int(map.height))?;
+            vm.set_attr(&d, "tile_width", vm.ctx.new_float(map.tile_size.x.into()))?;
+            vm.set_attr(&d, "tile_height", vm.ctx.new_float(map.tile_size.y.into()))?;
+            vm.set_attr(&d, "layers", vm.ctx.new_list(layers))?;
+            vm.set_attr(&d, "properties", properties_to_py(&map.properties, vm)?)?;
+            Ok(d)
+        })
+    }
+
+    /// The objects in an object layer, with their positions in the world
+    #[pyfunction]
+    fn tilemap_objects(
+        name: PyStrRef,
+        layer: LayerRef,
+        vm: &VirtualMachine,
+    ) -> PyResult<PyObjectRef> {
+        SPRITES.with(|r| {
+            let resources = r.borrow();
+            let map = get_tilemap(&resources, name.borrow_value(), vm)?;
+            let layer = get_layer(map, &layer, vm)?;
+            let objects = match &layer.content {
+                LayerContent::Objects(objects) => objects,
+                LayerContent::Tiles(_) => {
+                    return Err(vm.new_type_error(format!(
+                        "layer {:?} is a tile layer, not an object layer",
+                        layer.name
+                    )))
+                }
+            };
+
+            let objects = objects
+                .iter()
+                .map(|o| {
+                    let rect = Rectangle::new(o.rect.pos + layer.offset, o.rect.size);
+                    let points = |points: &[Vector]| {
+                        let points = points
+                            .iter()
+                            .map(|p| new_py_point(vm, *p + layer.offset))
+                            .collect();
+                        vm.ctx.new_list(points)
+                    };
+                    let (shape, points) = match &o.shape {
+                        ObjectShape::Rectangle => ("rectangle", vm.ctx.none()),
+                        ObjectShape::Ellipse => ("ellipse", vm.ctx.none()),
+                        ObjectShape::Point => ("point", vm.ctx.none()),
+                        ObjectShape::Polygon(p) => ("polygon", points(p)),
+                        ObjectShape::Polyline(p) => ("polyline", points(p)),
+                        ObjectShape::Tile(_) => ("tile", vm.ctx.none()),
+                    };
+                    let gid = match o.shape {
+                        ObjectShape::Tile(gid) => vm.ctx.new_int(tilemap::strip_flags(gid)),
+                        _ => vm.ctx.none(),
+                    };
+                    let d = vm.ctx.new_namespace();
+                    vm.set_attr(&d, "id", vm.ctx.new_int(o.id))?;
+                    vm.set_attr(&d, "name", vm.ctx.new_str(o.name.clone()))?;
+                    vm.set_attr(&d, "type", vm.ctx.new_str(o.kind.clone()))?;
+                    vm.set_attr(&d, "shape", vm.ctx.new_str(shape))?;
+                    vm.set_attr(&d, "x", vm.ctx.new_float(rect.pos.x.into()))?;
+                    vm.set_attr(&d, "y", vm.ctx.new_float(rect.pos.y.into()))?;
+                    vm.set_attr(&d, "width", vm.ctx.new_float(rect.size.x.into()))?;
+                    vm.set_attr(&d, "height", vm.ctx.new_float(rect.size.y.into()))?;
+                    vm.set_attr(&d, "rect", new_py_rect(vm, rect))?;
+                    vm.set_attr(&d, "rotation", vm.ctx.new_float(o.rotation.into()))?;
+                    vm.set_attr(&d, "points", points)?;
+                    vm.set_attr(&d, "gid", gid)?;
+                    vm.set_attr(&d, "properties", properties_to_py(&o.properties, vm)?)?;
+                    Ok(d)
+                })
+                .collect::<PyResult<_>>()?;
+            Ok(vm.ctx.new_list(objects))
+        })
+    }
+}
+
+/// Finish drawing to a surface that was left as the target at the end of a frame
+pub(crate) fn draw_to_window(
+    gfx: &mut Graphics,
+    resources: &Resources,
+    state: &mut crate::State,
+) -> QsResult<()> {
+    let name = match state.target.take() {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+    if let Some((surface, _)) = resources.surfaces.get(&name) {
+        gfx.flush_surface(surface)?;
+    }
+    gfx.set_camera_size(state.camera_size);
+    gfx.set_view(state.view);
+    Ok(())
+}
+
+pub(crate) fn key_index(name: &str) -> Option<usize> {
+    KEY_INDEX.get(name).copied()
+}
+
+pub(crate) fn parse_key(name: &str) -> Option<Key> {
+    key_index(name).map(|i| KEY_LIST[i])
+}
+
+pub(crate) fn key_name(key: Key) -> &'static str {
+    &KEY_NAMES[key as usize]
 }
 
 // has to be outside of the pymodule or the attribute macro hangs forever :/
@@ -576,4 +2448,16 @@ static KEY_LIST: [Key; NUM_KEYS] = [
     Key::WebHome, Key::WebRefresh, Key::WebSearch, Key::WebStop, Key::Yen
 ];
 
+/// The name of every key in `KEY_LIST`, which is its `Debug` output
+static KEY_NAMES: Lazy<Vec<String>> =
+    Lazy::new(|| KEY_LIST.iter().map(|key| format!("{:?}", key)).collect());
+
+static KEY_INDEX: Lazy<HashMap<&str, usize>> = Lazy::new(|| {
+    KEY_NAMES
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (name.as_str(), i))
+        .collect()
+});
+
 pub const NUM_KEYS: usize = 158;
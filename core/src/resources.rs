@@ -1,4 +1,6 @@
+use crate::atlas::{Atlas, Region};
 use crate::prelude::*;
+use crate::tilemap::Tilemap;
 use std::collections::{hash_map, HashMap};
 
 // imgs, anims, sounds
@@ -8,6 +10,17 @@ pub struct ResourceConfig {
     pub anims: Vec<(String, String, (usize, f64))>,
     pub sounds: Vec<(String, String)>,
     pub fonts: Vec<(String, String, f32)>,
+    /// name, image, data file
+    pub atlases: Vec<(String, String, String)>,
+    pub tilemaps: Vec<(String, String)>,
+}
+
+pub const DEFAULT_FONT_SIZE: f32 = 10.0;
+
+/// The font used when no other font is given
+pub fn default_font(gfx: &Graphics) -> QsResult<FontRenderer> {
+    VectorFont::from_slice(include_bytes!("../../include/VGATypewriter.ttf"))
+        .to_renderer(gfx, DEFAULT_FONT_SIZE)
 }
 
 pub struct Resources {
@@ -15,6 +28,11 @@ pub struct Resources {
     pub anims: HashMap<String, Animation>,
     pub sounds: HashMap<String, Sound>,
     pub fonts: HashMap<String, (FontRenderer, f32)>,
+    pub atlases: HashMap<String, Atlas>,
+    pub tilemaps: HashMap<String, Tilemap>,
+    /// Images that can be drawn to, made by `qs.create_surface()`, along with the image each one
+    /// draws to since quicksilver doesn't hand it back
+    pub surfaces: HashMap<String, (Surface, Image)>,
 }
 
 impl Resources {
@@ -24,6 +42,8 @@ impl Resources {
             anims,
             sounds,
             fonts,
+            atlases,
+            tilemaps,
         }: ResourceConfig,
         gfx: &Graphics,
     ) -> anyhow::Result<Self> {
@@ -51,8 +71,28 @@ impl Resources {
                 Ok::<_, anyhow::Error>((name, (font, size)))
             }));
 
-        let (anims, imgs, sounds, fonts) =
-            futures::try_join!(anim_futs, img_futs, sound_futs, font_futs)?;
+        let atlas_futs =
+            future::try_join_all(atlases.into_iter().map(|(name, image, data)| async move {
+                let atlas = Atlas::load(gfx, image, data).await?;
+                Ok::<_, anyhow::Error>((name, atlas))
+            }));
+
+        let tilemap_futs =
+            future::try_join_all(tilemaps.into_iter().map(|(name, src)| async move {
+                let map = Tilemap::load(gfx, src).await?;
+                Ok::<_, anyhow::Error>((name, map))
+            }));
+
+        let (anims, imgs, sounds, fonts, atlases, tilemaps) = futures::try_join!(
+            anim_futs,
+            img_futs,
+            sound_futs,
+            font_futs,
+            atlas_futs,
+            tilemap_futs
+        )?;
+        let atlases = atlases.into_iter().collect();
+        let tilemaps = tilemaps.into_iter().collect();
         let anims = anims.into_iter().collect();
         let imgs = imgs.into_iter().collect();
         if !sounds.is_empty() {
@@ -61,22 +101,35 @@ impl Resources {
         let sounds = sounds.into_iter().collect();
         let mut fonts = fonts.into_iter().collect::<HashMap<_, _>>();
         if let hash_map::Entry::Vacant(v) = fonts.entry("default".to_owned()) {
-            v.insert((
-                VectorFont::from_slice(include_bytes!("../../include/VGATypewriter.ttf"))
-                    .to_renderer(gfx, 10.0)?,
-                10.0,
-            ));
+            v.insert((default_font(gfx)?, DEFAULT_FONT_SIZE));
         }
         Ok(Resources {
             imgs,
             anims,
             sounds,
             fonts,
+            atlases,
+            tilemaps,
+            surfaces: HashMap::new(),
         })
     }
 
+    /// A loaded image, or the contents of a surface
     pub fn get_img(&self, name: &str) -> Option<&Image> {
-        self.imgs.get(name)
+        self.imgs
+            .get(name)
+            .or_else(|| self.surfaces.get(name).map(|(_, image)| image))
+    }
+
+    /// An image, or a region of an atlas named like "atlas/region"
+    pub fn get_sprite(&self, name: &str) -> Option<(&Image, Region)> {
+        if let Some(image) = self.get_img(name) {
+            return Some((image, Region::whole(image)));
+        }
+        let slash = name.find('/')?;
+        let atlas = self.atlases.get(&name[..slash])?;
+        let region = atlas.regions.get(&name[slash + 1..])?;
+        Some((&atlas.image, *region))
     }
 
     pub fn get_sound(&self, name: &str) -> Option<&Sound> {
@@ -100,6 +153,10 @@ impl Resources {
         }
     }
 
+    pub fn get_tilemap(&self, name: &str) -> Option<&Tilemap> {
+        self.tilemaps.get(name)
+    }
+
     pub fn get_font(&mut self, font_name: &str) -> Option<(&mut FontRenderer, f32)> {
         self.fonts.get_mut(font_name).map(|(f, pt)| (f, *pt))
     }
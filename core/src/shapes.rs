@@ -0,0 +1,583 @@
+//! tessellation for outlines and shapes that quicksilver can't fill by itself
+
+use crate::prelude::*;
+use lyon::math::{point, Point};
+use lyon::path::Path;
+use lyon::tessellation::{
+    BuffersBuilder, FillOptions, FillTessellator, FillVertex, LineCap, LineJoin, StrokeOptions,
+    StrokeTessellator, StrokeVertex, VertexBuffers,
+};
+use quicksilver::graphics::{Element, Mesh, Vertex};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Join {
+    Miter,
+    Round,
+    Bevel,
+}
+
+impl std::str::FromStr for Join {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "miter" => Ok(Join::Miter),
+            "round" => Ok(Join::Round),
+            "bevel" => Ok(Join::Bevel),
+            _ => Err(format!(
+                "unknown line join {:?}, expected miter, round or bevel",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Cap {
+    Butt,
+    Round,
+    Square,
+}
+
+impl std::str::FromStr for Cap {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "butt" => Ok(Cap::Butt),
+            "round" => Ok(Cap::Round),
+            "square" => Ok(Cap::Square),
+            _ => Err(format!(
+                "unknown line cap {:?}, expected butt, round or square",
+                s
+            )),
+        }
+    }
+}
+
+pub struct StrokeStyle {
+    pub thickness: f32,
+    pub join: Join,
+    pub cap: Cap,
+    /// Lengths of alternating dashes and gaps, or empty for a solid line
+    pub dash: Vec<f32>,
+    /// How far into the dash pattern the line starts
+    pub dash_offset: f32,
+}
+
+/// How a shape is colored in, in the shape's own coordinates before it's transformed
+#[derive(Clone)]
+pub enum Paint {
+    Solid(Color),
+    Linear {
+        start: Vector,
+        end: Vector,
+        stops: Vec<(f32, Color)>,
+    },
+    Radial {
+        center: Vector,
+        radius: f32,
+        stops: Vec<(f32, Color)>,
+    },
+    /// A color for each corner of the shape, blended across the inside
+    Vertices(Vec<(Vector, Color)>),
+}
+
+fn mix(a: Color, b: Color, t: f32) -> Color {
+    Color {
+        r: a.r + (b.r - a.r) * t,
+        g: a.g + (b.g - a.g) * t,
+        b: a.b + (b.b - a.b) * t,
+        a: a.a + (b.a - a.a) * t,
+    }
+}
+
+/// The color `t` of the way along the gradient, where the stops are sorted by offset
+fn gradient(stops: &[(f32, Color)], t: f32) -> Color {
+    match stops.iter().position(|(offset, _)| *offset > t) {
+        Some(0) => stops[0].1,
+        Some(i) => {
+            let (o0, c0) = stops[i - 1];
+            let (o1, c1) = stops[i];
+            mix(c0, c1, (t - o0) / (o1 - o0))
+        }
+        None => stops.last().map_or(Color::WHITE, |(_, c)| *c),
+    }
+}
+
+impl Paint {
+    pub fn at(&self, pos: Vector) -> Color {
+        match self {
+            Paint::Solid(color) => *color,
+            Paint::Linear { start, end, stops } => gradient(stops, linear_t(*start, *end, pos)),
+            Paint::Radial {
+                center,
+                radius,
+                stops,
+            } => gradient(stops, (pos - *center).len() / radius),
+            Paint::Vertices(corners) => {
+                // corners are usually hit exactly; anything in between, like where the edges of
+                // a polygon cross, gets a blend weighted by how close each corner is
+                let mut total = 0.0;
+                let mut sum = Color {
+                    r: 0.0,
+                    g: 0.0,
+                    b: 0.0,
+                    a: 0.0,
+                };
+                for (corner, color) in corners {
+                    let dist = (*corner - pos).len2();
+                    if dist < 1e-6 {
+                        return *color;
+                    }
+                    let w = 1.0 / dist;
+                    sum = Color {
+                        r: sum.r + color.r * w,
+                        g: sum.g + color.g * w,
+                        b: sum.b + color.b * w,
+                        a: sum.a + color.a * w,
+                    };
+                    total += w;
+                }
+                Color {
+                    r: sum.r / total,
+                    g: sum.g / total,
+                    b: sum.b / total,
+                    a: sum.a / total,
+                }
+            }
+        }
+    }
+
+    /// Whether the colors between `vertices` come out right without splitting the triangles of
+    /// the mesh up further
+    ///
+    /// A linear gradient only blends straight across the mesh between stops at 0 and 1, and
+    /// past its ends the color stops changing, so it needs every vertex to be between them.
+    fn is_linear(&self, vertices: &[Vector]) -> bool {
+        match self {
+            Paint::Solid(_) | Paint::Vertices(_) => true,
+            Paint::Linear { stops, .. } if stops.len() <= 1 => true,
+            Paint::Linear { start, end, stops } => {
+                stops.len() == 2
+                    && stops[0].0 == 0.0
+                    && stops[1].0 == 1.0
+                    && vertices
+                        .iter()
+                        .all(|pos| (0.0..=1.0).contains(&linear_t(*start, *end, *pos)))
+            }
+            Paint::Radial { .. } => false,
+        }
+    }
+}
+
+/// How far along the line from `start` to `end` `pos` is, measured at right angles to it
+fn linear_t(start: Vector, end: Vector, pos: Vector) -> f32 {
+    let line = end - start;
+    (pos - start).dot(line) / line.len2()
+}
+
+/// The longest a triangle edge can be in a mesh with a gradient that isn't linear
+const GRADIENT_STEP: f32 = 16.0;
+
+/// Split the triangles in half along their longest edges until every edge is short enough
+fn subdivide(vertices: &mut Vec<Vector>, indices: &[u32]) -> Vec<u32> {
+    let mut done = Vec::new();
+    let mut todo: Vec<[u32; 3]> = indices
+        .chunks_exact(3)
+        .map(|t| [t[0], t[1], t[2]])
+        .collect();
+    while let Some(tri) = todo.pop() {
+        let len =
+            |i: usize| (vertices[tri[(i + 1) % 3] as usize] - vertices[tri[i] as usize]).len2();
+        let longest = (0..3)
+            .max_by(|a, b| {
+                len(*a)
+                    .partial_cmp(&len(*b))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap();
+        if len(longest) <= GRADIENT_STEP * GRADIENT_STEP {
+            done.extend_from_slice(&tri);
+            continue;
+        }
+        let (a, b, c) = (tri[longest], tri[(longest + 1) % 3], tri[(longest + 2) % 3]);
+        let mid = (vertices[a as usize] + vertices[b as usize]) / 2.0;
+        vertices.push(mid);
+        let m = (vertices.len() - 1) as u32;
+        todo.push([a, m, c]);
+        todo.push([m, b, c]);
+    }
+    done
+}
+
+/// A run of connected points, which is a polygon if it's closed
+#[derive(Clone)]
+pub struct Contour {
+    pub points: Vec<Vector>,
+    pub closed: bool,
+}
+
+impl Contour {
+    pub fn closed(points: Vec<Vector>) -> Self {
+        Contour {
+            points,
+            closed: true,
+        }
+    }
+
+    pub fn open(points: Vec<Vector>) -> Self {
+        Contour {
+            points,
+            closed: false,
+        }
+    }
+
+    fn segments(&self) -> impl Iterator<Item = (Vector, Vector)> + '_ {
+        let closing = if self.closed {
+            self.points
+                .last()
+                .copied()
+                .zip(self.points.first().copied())
+        } else {
+            None
+        };
+        self.points.windows(2).map(|w| (w[0], w[1])).chain(closing)
+    }
+}
+
+/// How many straight segments to approximate a curve of `radius` sweeping `degrees` with
+fn curve_segments(radius: f32, degrees: f32) -> usize {
+    let full = (radius.sqrt() * 8.0).max(12.0).min(128.0);
+    let turns = degrees.abs().min(360.0) / 360.0;
+    ((full * turns).ceil() as usize).max(1)
+}
+
+/// Points along an elliptical arc, in degrees clockwise from the right, including both ends
+///
+/// Arcs sweeping more than a full turn stop after one, since going round again draws nothing new.
+pub fn arc(center: Vector, radii: Vector, start: f32, end: f32) -> Vec<Vector> {
+    let sweep = (end - start).max(-360.0).min(360.0);
+    let n = curve_segments(radii.x.max(radii.y), sweep);
+    (0..=n)
+        .map(|i| {
+            let angle = (start + sweep * i as f32 / n as f32).to_radians();
+            center + Vector::new(radii.x * angle.cos(), radii.y * angle.sin())
+        })
+        .collect()
+}
+
+pub fn ellipse(center: Vector, radii: Vector) -> Vec<Vector> {
+    let mut points = arc(center, radii, 0.0, 360.0);
+    // the last point is the same as the first
+    points.pop();
+    points
+}
+
+/// A rectangle with its corners rounded off by quarter circles of `radius`, which is shrunk to
+/// fit if the rectangle is too small for it
+pub fn rounded_rect(rect: &Rectangle, radius: f32) -> Vec<Vector> {
+    let r = radius
+        .max(0.0)
+        .min(rect.size.x.abs() / 2.0)
+        .min(rect.size.y.abs() / 2.0);
+    let (tl, br) = (rect.pos, rect.pos + rect.size);
+    if r == 0.0 {
+        return vec![tl, Vector::new(br.x, tl.y), br, Vector::new(tl.x, br.y)];
+    }
+    let corners = [
+        (Vector::new(br.x - r, br.y - r), 0.0),
+        (Vector::new(tl.x + r, br.y - r), 90.0),
+        (Vector::new(tl.x + r, tl.y + r), 180.0),
+        (Vector::new(br.x - r, tl.y + r), 270.0),
+    ];
+    corners
+        .iter()
+        .flat_map(|(center, start)| arc(*center, Vector::new(r, r), *start, start + 90.0))
+        .collect()
+}
+
+/// Points along a quadratic bezier curve, not including `start`
+pub fn quad(start: Vector, ctrl: Vector, end: Vector) -> Vec<Vector> {
+    let n = curve_segments((ctrl - start).len() + (end - ctrl).len(), 90.0);
+    (1..=n)
+        .map(|i| {
+            let t = i as f32 / n as f32;
+            let u = 1.0 - t;
+            start * (u * u) + ctrl * (2.0 * u * t) + end * (t * t)
+        })
+        .collect()
+}
+
+/// Points along a cubic bezier curve, not including `start`
+pub fn cubic(start: Vector, ctrl1: Vector, ctrl2: Vector, end: Vector) -> Vec<Vector> {
+    let length = (ctrl1 - start).len() + (ctrl2 - ctrl1).len() + (end - ctrl2).len();
+    let n = curve_segments(length, 90.0);
+    (1..=n)
+        .map(|i| {
+            let t = i as f32 / n as f32;
+            let u = 1.0 - t;
+            start * (u * u * u)
+                + ctrl1 * (3.0 * u * u * t)
+                + ctrl2 * (3.0 * u * t * t)
+                + end * (t * t * t)
+        })
+        .collect()
+}
+
+/// A path built up a piece at a time like on an html canvas, with the curves flattened as
+/// they're added
+#[derive(Default)]
+pub struct PathBuilder {
+    contours: Vec<Contour>,
+    current: Vec<Vector>,
+}
+
+impl PathBuilder {
+    fn finish(&mut self, closed: bool) {
+        let points = std::mem::take(&mut self.current);
+        if points.len() > 1 {
+            self.contours.push(Contour { points, closed });
+        }
+    }
+
+    /// Where the next piece starts from, which is `p` if the path is empty
+    fn start(&mut self, p: Vector) -> Vector {
+        match self.current.last() {
+            Some(last) => *last,
+            None => {
+                self.current.push(p);
+                p
+            }
+        }
+    }
+
+    pub fn move_to(&mut self, p: Vector) {
+        self.finish(false);
+        self.current.push(p);
+    }
+
+    pub fn line_to(&mut self, p: Vector) {
+        self.start(p);
+        self.current.push(p);
+    }
+
+    pub fn quad_to(&mut self, ctrl: Vector, p: Vector) {
+        let start = self.start(ctrl);
+        self.current.extend(quad(start, ctrl, p));
+    }
+
+    pub fn cubic_to(&mut self, ctrl1: Vector, ctrl2: Vector, p: Vector) {
+        let start = self.start(ctrl1);
+        self.current.extend(cubic(start, ctrl1, ctrl2, p));
+    }
+
+    /// A straight line to the start of the arc, then along it
+    pub fn arc(&mut self, center: Vector, radius: f32, start: f32, end: f32) {
+        let points = arc(center, Vector::new(radius, radius), start, end);
+        self.start(points[0]);
+        self.current.extend(points);
+    }
+
+    /// Round off the corner at `p1` between the line from where the path is to `p1` and the line
+    /// from `p1` to `p2`, like `arcTo` on an html canvas: a straight line to where a circle of
+    /// `radius` touches the first line, then along the circle to where it touches the second
+    pub fn arc_to(&mut self, p1: Vector, p2: Vector, radius: f32) {
+        let p0 = self.start(p1);
+        let (d0, d2) = (p0 - p1, p2 - p1);
+        let cross = d0.x * d2.y - d0.y * d2.x;
+        // without a corner to round off, it's just a line
+        if radius == 0.0 || d0.len2() == 0.0 || d2.len2() == 0.0 || cross.abs() < 1e-6 {
+            self.current.push(p1);
+            return;
+        }
+        let (v0, v2) = (d0.normalize(), d2.normalize());
+        let half = v0.dot(v2).max(-1.0).min(1.0).acos() / 2.0;
+        let tangent = radius / half.tan();
+        let center = p1 + (v0 + v2).normalize() * (radius / half.sin());
+        let angle = |p: Vector| (p.y - center.y).atan2(p.x - center.x).to_degrees();
+        let (start, end) = (angle(p1 + v0 * tangent), angle(p1 + v2 * tangent));
+        // always the short way round, which is the side facing the corner
+        let sweep = (end - start + 180.0).rem_euclid(360.0) - 180.0;
+        self.arc(center, radius, start, start + sweep);
+    }
+
+    /// Join the current piece back up to where it started
+    pub fn close(&mut self) {
+        self.finish(true);
+    }
+
+    /// Every piece of the path so far, with the one being built left open
+    pub fn contours(&self) -> Vec<Contour> {
+        let mut contours = self.contours.clone();
+        if self.current.len() > 1 {
+            contours.push(Contour::open(self.current.clone()));
+        }
+        contours
+    }
+}
+
+fn to_point(v: Vector) -> Point {
+    point(v.x, v.y)
+}
+
+fn build_path(contours: &[Contour]) -> Path {
+    let mut builder = Path::builder();
+    for contour in contours {
+        let mut points = contour.points.iter().copied().map(to_point);
+        if let Some(first) = points.next() {
+            builder.begin(first);
+            points.for_each(|p| {
+                builder.line_to(p);
+            });
+            builder.end(contour.closed);
+        }
+    }
+    builder.build()
+}
+
+fn to_mesh(buffers: VertexBuffers<Point, u32>, paint: &Paint) -> Mesh {
+    let mut positions: Vec<Vector> = buffers
+        .vertices
+        .iter()
+        .map(|p| Vector::new(p.x, p.y))
+        .collect();
+    let indices = if paint.is_linear(&positions) {
+        buffers.indices
+    } else {
+        // shared edges are split at the same midpoints, so no cracks open up between triangles
+        subdivide(&mut positions, &buffers.indices)
+    };
+    Mesh {
+        vertices: positions
+            .into_iter()
+            .map(|pos| Vertex {
+                pos,
+                uv: None,
+                color: paint.at(pos),
+            })
+            .collect(),
+        elements: indices
+            .chunks_exact(3)
+            .map(|t| Element::Triangle([t[0], t[1], t[2]]))
+            .collect(),
+        image: None,
+    }
+}
+
+/// Points that aren't finite would never be split small enough for a gradient, or finish being
+/// cut into dashes
+fn check_finite(contours: &[Contour]) -> Result<(), String> {
+    let finite = |v: &Vector| v.x.is_finite() && v.y.is_finite();
+    match contours.iter().flat_map(|c| &c.points).find(|p| !finite(p)) {
+        Some(p) => Err(format!(
+            "shape has a point that isn't a finite number, {:?}",
+            p
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Triangulate the inside of the contours, which may be concave or overlap each other
+pub fn fill(contours: &[Contour], paint: &Paint) -> Result<Mesh, String> {
+    check_finite(contours)?;
+    let mut buffers = VertexBuffers::new();
+    FillTessellator::new()
+        .tessellate_path(
+            &build_path(contours),
+            &FillOptions::default(),
+            &mut BuffersBuilder::new(&mut buffers, |v: FillVertex| v.position()),
+        )
+        .map_err(|e| format!("couldn't fill shape: {:?}", e))?;
+    Ok(to_mesh(buffers, paint))
+}
+
+/// Triangulate a line of the given style along the contours
+pub fn stroke(contours: &[Contour], style: &StrokeStyle, paint: &Paint) -> Result<Mesh, String> {
+    check_finite(contours)?;
+    let dashed;
+    let contours = if style.dash.is_empty() {
+        contours
+    } else {
+        dashed = dash(contours, &style.dash, style.dash_offset);
+        &dashed
+    };
+    let options = StrokeOptions::default()
+        .with_line_width(style.thickness)
+        .with_line_join(match style.join {
+            Join::Miter => LineJoin::Miter,
+            Join::Round => LineJoin::Round,
+            Join::Bevel => LineJoin::Bevel,
+        })
+        .with_line_cap(match style.cap {
+            Cap::Butt => LineCap::Butt,
+            Cap::Round => LineCap::Round,
+            Cap::Square => LineCap::Square,
+        });
+    let mut buffers = VertexBuffers::new();
+    StrokeTessellator::new()
+        .tessellate_path(
+            &build_path(contours),
+            &options,
+            &mut BuffersBuilder::new(&mut buffers, |v: StrokeVertex| v.position()),
+        )
+        .map_err(|e| format!("couldn't stroke shape: {:?}", e))?;
+    Ok(to_mesh(buffers, paint))
+}
+
+/// The shortest a whole dash pattern can be, since a finer one would cut a line into so many
+/// pieces that stroking it never finishes
+pub const MIN_DASH_PATTERN: f32 = 0.01;
+
+/// Cut the contours up into the dashes of `pattern`, which restarts at the start of each one
+fn dash(contours: &[Contour], pattern: &[f32], offset: f32) -> Vec<Contour> {
+    let total: f32 = pattern.iter().sum();
+    let mut dashes = Vec::new();
+    for contour in contours {
+        // find where in the pattern the contour starts
+        let mut i = 0;
+        let mut left = pattern[0];
+        let mut skip = offset.rem_euclid(total);
+        while skip > 0.0 {
+            if skip < left {
+                left -= skip;
+                break;
+            }
+            skip -= left;
+            i = (i + 1) % pattern.len();
+            left = pattern[i];
+        }
+
+        let mut current = if i % 2 == 0 {
+            contour.points.first().map(|p| vec![*p])
+        } else {
+            None
+        };
+        for (mut start, end) in contour.segments() {
+            let mut len = (end - start).len();
+            while len > 0.0 {
+                let step = left.min(len);
+                let pos = start + (end - start) * (step / len);
+                if let Some(points) = &mut current {
+                    points.push(pos);
+                }
+                len -= step;
+                left -= step;
+                start = pos;
+                if left <= 0.0 {
+                    // this part of the pattern is used up, so flip between dash and gap
+                    i = (i + 1) % pattern.len();
+                    left = pattern[i];
+                    match current.take() {
+                        Some(points) => dashes.push(Contour::open(points)),
+                        None => current = Some(vec![pos]),
+                    }
+                }
+            }
+        }
+        if let Some(points) = current {
+            if points.len() > 1 {
+                dashes.push(Contour::open(points));
+            }
+        }
+    }
+    dashes
+}
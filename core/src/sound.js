@@ -1,5 +1,14 @@
+let ctx;
+
+function context() {
+    if (!ctx) {
+        ctx = new AudioContext();
+    }
+    return ctx;
+}
+
 export async function load(path) {
-    const ctx = new AudioContext();
+    const ctx = context();
     const data = await fetch(path).then((res) => res.arrayBuffer());
     const audio = await ctx.decodeAudioData(data);
     return {
@@ -16,3 +25,22 @@ export async function load(path) {
         },
     };
 }
+
+export function pause() {
+    if (ctx) {
+        ctx.suspend();
+    }
+}
+
+export function resume() {
+    if (ctx) {
+        ctx.resume();
+    }
+}
+
+export function stop() {
+    if (ctx) {
+        ctx.close();
+        ctx = undefined;
+    }
+}
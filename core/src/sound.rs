@@ -12,12 +12,14 @@ pub struct Sound {
 mod audio {
     use super::*;
     use once_cell::unsync::OnceCell;
+    use std::cell::RefCell;
     use std::io::Cursor;
     use std::sync::Arc;
 
     std::thread_local! {
         static STREAM: OnceCell<(rodio::OutputStream, rodio::OutputStreamHandle)> =
             OnceCell::new();
+        static SINKS: RefCell<Vec<rodio::Sink>> = RefCell::new(Vec::new());
     }
     #[derive(Clone)]
     pub struct AudioData(Arc<Vec<u8>>);
@@ -39,7 +41,12 @@ mod audio {
                     stream.get_or_try_init(rodio::OutputStream::try_default)?;
                 let sink = stream_handle.play_once(Cursor::new(self.clone()))?;
                 sink.set_volume(volume);
-                sink.detach();
+                SINKS.with(|sinks| {
+                    let mut sinks = sinks.borrow_mut();
+                    // forget about sounds that have finished playing
+                    sinks.retain(|sink| !sink.empty());
+                    sinks.push(sink);
+                });
                 Ok(())
             })
         }
@@ -52,6 +59,18 @@ mod audio {
             }
         })
     }
+
+    pub fn pause() {
+        SINKS.with(|sinks| sinks.borrow().iter().for_each(rodio::Sink::pause))
+    }
+
+    pub fn resume() {
+        SINKS.with(|sinks| sinks.borrow().iter().for_each(rodio::Sink::play))
+    }
+
+    pub fn stop() {
+        SINKS.with(|sinks| sinks.borrow_mut().drain(..).for_each(|sink| sink.stop()))
+    }
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -70,6 +89,13 @@ mod audio {
         pub type AudioData;
         #[wasm_bindgen(method, catch)]
         fn _play(this: &AudioData, volume: f32) -> Result<(), JsValue>;
+
+        #[wasm_bindgen(js_name = pause)]
+        fn js_pause();
+        #[wasm_bindgen(js_name = resume)]
+        fn js_resume();
+        #[wasm_bindgen(js_name = stop)]
+        fn js_stop();
     }
 
     impl AudioData {
@@ -90,6 +116,18 @@ mod audio {
     }
 
     pub fn init() {}
+
+    pub fn pause() {
+        js_pause()
+    }
+
+    pub fn resume() {
+        js_resume()
+    }
+
+    pub fn stop() {
+        js_stop()
+    }
 }
 
 impl Sound {
@@ -132,4 +170,19 @@ impl Sound {
     pub(crate) fn init() {
         audio::init();
     }
+
+    /// Pause every sound that is currently playing
+    pub(crate) fn pause_all() {
+        audio::pause();
+    }
+
+    /// Resume the sounds paused by `pause_all`
+    pub(crate) fn resume_all() {
+        audio::resume();
+    }
+
+    /// Stop every sound that is currently playing, paused or not
+    pub(crate) fn stop_all() {
+        audio::stop();
+    }
 }
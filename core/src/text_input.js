@@ -0,0 +1,62 @@
+const queue = [];
+let field = null;
+
+function canvas() {
+    return document.querySelector("canvas");
+}
+
+// IME composition only happens in an editable element, so text input goes through a hidden
+// textarea, with the keys it receives passed on to the game
+function install() {
+    if (field) {
+        return;
+    }
+    field = document.createElement("textarea");
+    field.setAttribute("autocomplete", "off");
+    field.setAttribute("autocapitalize", "off");
+    field.style.position = "fixed";
+    field.style.left = "-1000px";
+    field.style.opacity = "0";
+    document.body.appendChild(field);
+
+    for (const type of ["keydown", "keyup", "keypress"]) {
+        field.addEventListener(type, (e) => {
+            // keyCode 229 is the key that starts a composition
+            if (e.isComposing || e.keyCode === 229) {
+                return;
+            }
+            // let the browser turn a paste shortcut into a paste event
+            if (!((e.ctrlKey || e.metaKey) && e.key === "v")) {
+                e.preventDefault();
+            }
+            canvas().dispatchEvent(new KeyboardEvent(type, e));
+        });
+    }
+    field.addEventListener("compositionstart", () => queue.push("start", ""));
+    field.addEventListener("compositionupdate", (e) => queue.push("update", e.data));
+    field.addEventListener("compositionend", (e) => {
+        queue.push("end", e.data);
+        field.value = "";
+    });
+    field.addEventListener("paste", (e) => {
+        e.preventDefault();
+        queue.push("paste", e.clipboardData.getData("text"));
+    });
+}
+
+export function start() {
+    install();
+    field.value = "";
+    field.focus();
+}
+
+export function stop() {
+    if (field && document.activeElement === field) {
+        field.blur();
+        canvas().focus();
+    }
+}
+
+export function drain() {
+    return queue.splice(0, queue.length);
+}
@@ -0,0 +1,288 @@
+//! an editable line of text, so games don't have to handle backspace and cursor movement in
+//! python
+
+use crate::keyboard::Modifiers;
+use crate::prelude::*;
+use crate::{clipboard, error};
+
+pub struct TextInput {
+    text: Vec<char>,
+    /// The index of the character the cursor is in front of
+    cursor: usize,
+    /// The other end of the selection, if anything is selected
+    anchor: Option<usize>,
+    /// Text the IME is still composing, which isn't part of the value yet
+    pub composition: String,
+    /// Whether the value or cursor changed since the last `text_input` event
+    pub changed: bool,
+}
+
+impl TextInput {
+    pub fn new(text: &str) -> Self {
+        let text: Vec<char> = text.chars().collect();
+        TextInput {
+            cursor: text.len(),
+            text,
+            anchor: None,
+            composition: String::new(),
+            changed: false,
+        }
+    }
+
+    pub fn value(&self) -> String {
+        self.text.iter().collect()
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// The selected range of characters, if it isn't empty
+    pub fn selection(&self) -> Option<(usize, usize)> {
+        match self.anchor {
+            Some(a) if a != self.cursor => Some((a.min(self.cursor), a.max(self.cursor))),
+            _ => None,
+        }
+    }
+
+    fn selected_text(&self) -> Option<String> {
+        self.selection()
+            .map(|(start, end)| self.text[start..end].iter().collect())
+    }
+
+    fn delete_selection(&mut self) -> bool {
+        match self.selection() {
+            Some((start, end)) => {
+                self.text.drain(start..end);
+                self.cursor = start;
+                self.anchor = None;
+                true
+            }
+            None => {
+                self.anchor = None;
+                false
+            }
+        }
+    }
+
+    /// Replace the selection with `s`, leaving out anything that can't go in a single line
+    pub fn insert(&mut self, s: &str) {
+        self.delete_selection();
+        let chars: Vec<char> = s.chars().filter(|c| !c.is_control()).collect();
+        let n = chars.len();
+        self.text.splice(self.cursor..self.cursor, chars);
+        self.cursor += n;
+        self.changed = true;
+    }
+
+    fn move_to(&mut self, pos: usize, select: bool) {
+        if select {
+            self.anchor.get_or_insert(self.cursor);
+        } else {
+            self.anchor = None;
+        }
+        self.cursor = pos;
+    }
+
+    /// Handle an editing key, returning whether the key meant anything to the text input
+    pub fn key(&mut self, key: Key, mods: Modifiers) -> bool {
+        let select = mods.shift;
+        match key {
+            Key::Back => {
+                if !self.delete_selection() && self.cursor > 0 {
+                    self.cursor -= 1;
+                    self.text.remove(self.cursor);
+                }
+            }
+            Key::Delete => {
+                if !self.delete_selection() && self.cursor < self.text.len() {
+                    self.text.remove(self.cursor);
+                }
+            }
+            Key::Left => match self.selection() {
+                Some((start, _)) if !select => self.move_to(start, false),
+                _ => self.move_to(self.cursor.saturating_sub(1), select),
+            },
+            Key::Right => match self.selection() {
+                Some((_, end)) if !select => self.move_to(end, false),
+                _ => self.move_to((self.cursor + 1).min(self.text.len()), select),
+            },
+            Key::Home => self.move_to(0, select),
+            Key::End => self.move_to(self.text.len(), select),
+            Key::A if mods.ctrl || mods.logo => {
+                self.anchor = Some(0);
+                self.cursor = self.text.len();
+            }
+            Key::C | Key::X if mods.ctrl || mods.logo => {
+                let text = match self.selected_text() {
+                    Some(text) => text,
+                    None => return true,
+                };
+                if let Err(e) = clipboard::set(&text) {
+                    error::report(&anyhow::anyhow!("couldn't copy to the clipboard: {}", e));
+                }
+                if key == Key::C {
+                    return true;
+                }
+                self.delete_selection();
+            }
+            Key::V if mods.ctrl || mods.logo => match clipboard::paste() {
+                Some(text) => self.insert(&text),
+                None => return true,
+            },
+            _ => return false,
+        }
+        self.changed = true;
+        true
+    }
+}
+
+/// A step of IME composition, which only the web reports
+#[cfg_attr(not(target_arch = "wasm32"), allow(unused))]
+pub enum ImeEvent {
+    Start,
+    Update(String),
+    End(String),
+    /// Text pasted through the browser, which is the only way to read the clipboard there
+    /// without asking for permission
+    Paste(String),
+}
+
+#[cfg(target_arch = "wasm32")]
+mod platform {
+    use super::*;
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen(module = "/src/text_input.js")]
+    extern "C" {
+        fn start();
+        fn stop();
+        fn drain() -> js_sys::Array;
+    }
+
+    pub fn set_active(active: bool) {
+        if active {
+            start()
+        } else {
+            stop()
+        }
+    }
+
+    pub fn poll() -> Vec<ImeEvent> {
+        let queue: Vec<String> = drain().iter().filter_map(|v| v.as_string()).collect();
+        queue
+            .chunks_exact(2)
+            .map(|e| match e[0].as_str() {
+                "start" => ImeEvent::Start,
+                "update" => ImeEvent::Update(e[1].clone()),
+                "end" => ImeEvent::End(e[1].clone()),
+                _ => ImeEvent::Paste(e[1].clone()),
+            })
+            .collect()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod platform {
+    use super::*;
+
+    // quicksilver doesn't report IME composition on desktop, but composed characters still
+    // arrive as ReceivedCharacter events
+    pub fn set_active(_active: bool) {}
+
+    pub fn poll() -> Vec<ImeEvent> {
+        Vec::new()
+    }
+}
+
+/// Let the platform know whether text is being entered, so it can show IME candidates
+pub fn set_active(active: bool) {
+    platform::set_active(active)
+}
+
+/// Take the IME events that happened since the last call
+pub fn poll() -> Vec<ImeEvent> {
+    platform::poll()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NONE: Modifiers = Modifiers {
+        shift: false,
+        ctrl: false,
+        alt: false,
+        logo: false,
+    };
+    const SHIFT: Modifiers = Modifiers {
+        shift: true,
+        ..NONE
+    };
+
+    #[test]
+    fn moves_and_selects() {
+        let mut input = TextInput::new("hello");
+        assert_eq!(input.cursor(), 5);
+        input.key(Key::Left, NONE);
+        input.key(Key::Left, SHIFT);
+        input.key(Key::Left, SHIFT);
+        assert_eq!((input.cursor(), input.selection()), (2, Some((2, 4))));
+        // without shift, moving collapses the selection to the side moved towards
+        input.key(Key::Right, NONE);
+        assert_eq!((input.cursor(), input.selection()), (4, None));
+        input.key(Key::Home, SHIFT);
+        assert_eq!(input.selection(), Some((0, 4)));
+        input.key(Key::End, NONE);
+        assert_eq!((input.cursor(), input.selection()), (5, None));
+        input.key(Key::Right, NONE);
+        assert_eq!(input.cursor(), 5);
+    }
+
+    #[test]
+    fn backspace_removes_whole_characters() {
+        let mut input = TextInput::new("añ😀");
+        input.key(Key::Back, NONE);
+        assert_eq!(input.value(), "añ");
+        input.key(Key::Left, NONE);
+        input.key(Key::Back, NONE);
+        assert_eq!((input.value(), input.cursor()), ("ñ".to_owned(), 0));
+        input.key(Key::Back, NONE);
+        assert_eq!(input.value(), "ñ");
+        input.key(Key::Delete, NONE);
+        assert_eq!(input.value(), "");
+    }
+
+    #[test]
+    fn backspace_deletes_the_selection() {
+        let mut input = TextInput::new("日本語です");
+        input.key(Key::Left, NONE);
+        input.key(Key::Left, SHIFT);
+        input.key(Key::Left, SHIFT);
+        input.key(Key::Back, NONE);
+        assert_eq!((input.value(), input.cursor()), ("日本す".to_owned(), 2));
+    }
+
+    #[test]
+    fn paste_replaces_the_selection() {
+        let mut input = TextInput::new("one two three");
+        input.key(Key::Home, NONE);
+        for _ in 0..4 {
+            input.key(Key::Right, NONE);
+        }
+        for _ in 0..3 {
+            input.key(Key::Right, SHIFT);
+        }
+        input.insert("2\n");
+        assert_eq!(
+            (input.value(), input.cursor()),
+            ("one 2 three".to_owned(), 5)
+        );
+        assert_eq!(input.selection(), None);
+
+        let ctrl = Modifiers { ctrl: true, ..NONE };
+        input.key(Key::A, ctrl);
+        input.insert("é");
+        assert_eq!((input.value(), input.cursor()), ("é".to_owned(), 1));
+    }
+}
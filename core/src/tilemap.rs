@@ -0,0 +1,1144 @@
+//! Maps made with the Tiled editor, in its .tmx (XML) or .json formats
+
+use crate::prelude::*;
+use anyhow::{anyhow, bail, Context};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Read;
+use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The top bits of a tile's gid say how it's flipped
+pub const FLIP_X: u32 = 0x8000_0000;
+pub const FLIP_Y: u32 = 0x4000_0000;
+pub const FLIP_DIAGONAL: u32 = 0x2000_0000;
+const FLAGS: u32 = 0xf000_0000;
+
+/// Remove the flip flags from a gid
+pub fn strip_flags(gid: u32) -> u32 {
+    gid & !FLAGS
+}
+
+#[derive(Clone, Debug)]
+pub enum Property {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    /// Strings, and every other type of property, such as colors and files
+    String(String),
+}
+
+pub type Properties = HashMap<String, Property>;
+
+impl Property {
+    fn parse(kind: &str, value: &str) -> anyhow::Result<Property> {
+        Ok(match kind {
+            "bool" => Property::Bool(value.parse()?),
+            "int" | "object" => Property::Int(value.parse()?),
+            "float" => Property::Float(value.parse()?),
+            _ => Property::String(value.to_owned()),
+        })
+    }
+
+    fn from_json(kind: &str, value: serde_json::Value) -> Property {
+        use serde_json::Value;
+        match (kind, value) {
+            (_, Value::Bool(b)) => Property::Bool(b),
+            ("float", Value::Number(n)) => Property::Float(n.as_f64().unwrap_or_default()),
+            (_, Value::Number(n)) => match n.as_i64() {
+                Some(i) => Property::Int(i),
+                None => Property::Float(n.as_f64().unwrap_or_default()),
+            },
+            (_, Value::String(s)) => Property::String(s),
+            (_, value) => Property::String(value.to_string()),
+        }
+    }
+}
+
+pub struct Tileset {
+    pub name: String,
+    pub first_gid: u32,
+    pub tile_count: u32,
+    pub columns: u32,
+    pub tile_size: Vector,
+    margin: u32,
+    spacing: u32,
+    pub image: Image,
+    /// Custom properties of single tiles, by their id in the tileset
+    pub tile_properties: HashMap<u32, Properties>,
+}
+
+impl Tileset {
+    /// Where a tile is in the tileset's image
+    pub fn region(&self, id: u32) -> Rectangle {
+        let (col, row) = (id % self.columns, id / self.columns);
+        let step = |n: u32, size: f32| self.margin as f32 + n as f32 * (self.spacing as f32 + size);
+        Rectangle::new(
+            Vector::new(step(col, self.tile_size.x), step(row, self.tile_size.y)),
+            self.tile_size,
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ObjectShape {
+    Rectangle,
+    Ellipse,
+    Point,
+    Polygon(Vec<Vector>),
+    Polyline(Vec<Vector>),
+    /// A tile placed freely, with its gid
+    Tile(u32),
+}
+
+pub struct Object {
+    pub id: u32,
+    pub name: String,
+    pub kind: String,
+    /// The object's bounds, with tile objects moved so `pos` is their top left like the rest
+    pub rect: Rectangle,
+    /// In degrees, clockwise around the object's position in Tiled
+    pub rotation: f32,
+    pub shape: ObjectShape,
+    pub properties: Properties,
+}
+
+pub enum LayerContent {
+    /// The gid of each tile, row by row, where 0 is an empty tile
+    Tiles(Vec<u32>),
+    Objects(Vec<Object>),
+}
+
+/// A tile or object layer. Groups are flattened into the layers inside them, and image layers
+/// are skipped.
+pub struct Layer {
+    pub name: String,
+    /// Whether the layer and the groups it's in are shown in Tiled
+    pub visible: bool,
+    pub opacity: f32,
+    pub offset: Vector,
+    pub properties: Properties,
+    pub content: LayerContent,
+}
+
+pub struct Tilemap {
+    /// In tiles
+    pub width: u32,
+    pub height: u32,
+    pub tile_size: Vector,
+    pub tilesets: Vec<Tileset>,
+    pub layers: Vec<Layer>,
+    pub properties: Properties,
+}
+
+impl Tilemap {
+    pub async fn load(gfx: &Graphics, path: String) -> anyhow::Result<Tilemap> {
+        let def = async {
+            let data = load_file(&path).await?;
+            let def = if is_json(&path) {
+                parse_json_map(&data)?
+            } else {
+                parse_tmx_map(std::str::from_utf8(&data)?)?
+            };
+            let mut tilesets = Vec::new();
+            for tileset in def.tilesets {
+                tilesets.push(match tileset {
+                    TilesetRef::Def(mut def) => {
+                        def.image = relative(&path, &def.image);
+                        def
+                    }
+                    TilesetRef::External { first_gid, source } => {
+                        let source = relative(&path, &source);
+                        load_tileset(first_gid, &source)
+                            .await
+                            .with_context(|| format!("in tileset {:?}", source))?
+                    }
+                })
+            }
+            Ok::<_, anyhow::Error>(MapDef {
+                width: def.width,
+                height: def.height,
+                tile_size: def.tile_size,
+                tilesets,
+                layers: def.layers,
+                properties: def.properties,
+            })
+        };
+        let def = def
+            .await
+            .with_context(|| format!("couldn't load tilemap {:?}", path))?;
+
+        let tilesets = future::try_join_all(def.tilesets.into_iter().map(|def| async move {
+            let image = Image::load(gfx, &def.image).await?;
+            Ok::<_, anyhow::Error>(def.into_tileset(image))
+        }))
+        .await?;
+        Ok(Tilemap {
+            width: def.width,
+            height: def.height,
+            tile_size: def.tile_size,
+            tilesets,
+            layers: def.layers,
+            properties: def.properties,
+        })
+    }
+
+    /// The tileset a gid comes from
+    pub fn tileset(&self, gid: u32) -> Option<(usize, &Tileset)> {
+        let gid = strip_flags(gid);
+        self.tilesets
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, t)| t.first_gid <= gid)
+            .filter(|(_, t)| gid - t.first_gid < t.tile_count)
+    }
+
+    /// The column and row of the tile at a position, if it's in the map
+    pub fn tile_pos(&self, layer: &Layer, pos: Vector) -> Option<(u32, u32)> {
+        let pos = pos - layer.offset;
+        let col = (pos.x / self.tile_size.x).floor();
+        let row = (pos.y / self.tile_size.y).floor();
+        // written so NaN is outside the map too
+        let inside = |n: f32, count: u32| n >= 0.0 && n < count as f32;
+        if !inside(col, self.width) || !inside(row, self.height) {
+            return None;
+        }
+        Some((col as u32, row as u32))
+    }
+
+    /// The columns and rows of the tiles that can be seen in `area`
+    pub fn visible(&self, layer: &Layer, area: Rectangle) -> (Range<u32>, Range<u32>) {
+        // tiles bigger than the grid stick out up and to the right of their cell
+        let overhang = self.tilesets.iter().fold(Vector::ZERO, |o, t| {
+            Vector::new(
+                o.x.max(t.tile_size.x - self.tile_size.x),
+                o.y.max(t.tile_size.y - self.tile_size.y),
+            )
+        });
+        let area = Rectangle::new(area.pos - layer.offset, area.size);
+        let cells = |start: f32, end: f32, size: f32, count: u32| {
+            let first = (start / size).floor().max(0.0) as u32;
+            let last = (end / size).ceil().max(0.0) as u32;
+            first.min(count)..last.min(count)
+        };
+        let (tl, br) = (area.pos, area.pos + area.size);
+        (
+            cells(tl.x - overhang.x, br.x, self.tile_size.x, self.width),
+            cells(tl.y, br.y + overhang.y, self.tile_size.y, self.height),
+        )
+    }
+}
+
+fn is_json(path: &str) -> bool {
+    path.ends_with(".json") || path.ends_with(".tmj") || path.ends_with(".tsj")
+}
+
+/// A path relative to the file `base`, which is how Tiled refers to tilesets and images
+fn relative(base: &str, path: &str) -> String {
+    match Path::new(base).parent() {
+        Some(dir) => dir.join(path).to_string_lossy().into_owned(),
+        None => path.to_owned(),
+    }
+}
+
+/// A tilemap before its tilesets' images have been loaded
+struct MapDef<T> {
+    width: u32,
+    height: u32,
+    tile_size: Vector,
+    tilesets: Vec<T>,
+    layers: Vec<Layer>,
+    properties: Properties,
+}
+
+enum TilesetRef {
+    Def(TilesetDef),
+    /// A tileset in its own file
+    External {
+        first_gid: u32,
+        source: String,
+    },
+}
+
+struct TilesetDef {
+    name: String,
+    first_gid: u32,
+    tile_count: Option<u32>,
+    columns: Option<u32>,
+    tile_size: Vector,
+    margin: u32,
+    spacing: u32,
+    image: String,
+    tile_properties: HashMap<u32, Properties>,
+}
+
+impl TilesetDef {
+    fn into_tileset(self, image: Image) -> Tileset {
+        // old versions of Tiled didn't save these. In u64 so that no margin or spacing can
+        // overflow, and the count always fits back in a u32 since it's at most the image size
+        let (margin, spacing) = (u64::from(self.margin), u64::from(self.spacing));
+        let fit = |image: f32, tile: f32| {
+            let space = (image as u64).saturating_sub(2 * margin) + spacing;
+            (space / (tile as u64 + spacing).max(1)) as u32
+        };
+        let size = image.size();
+        let columns = self
+            .columns
+            .unwrap_or_else(|| fit(size.x, self.tile_size.x))
+            .max(1);
+        let tile_count = self
+            .tile_count
+            .unwrap_or_else(|| columns.saturating_mul(fit(size.y, self.tile_size.y)));
+        Tileset {
+            name: self.name,
+            first_gid: self.first_gid,
+            tile_count,
+            columns,
+            tile_size: self.tile_size,
+            margin: self.margin,
+            spacing: self.spacing,
+            image,
+            tile_properties: self.tile_properties,
+        }
+    }
+}
+
+async fn load_tileset(first_gid: u32, path: &str) -> anyhow::Result<TilesetDef> {
+    let data = load_file(path).await?;
+    let mut def = if is_json(path) {
+        serde_json::from_slice::<JsonTileset>(&data)?.into_def()?
+    } else {
+        let text = std::str::from_utf8(&data)?;
+        let doc = roxmltree::Document::parse(text)?;
+        tmx_tileset(doc.root_element())?
+    };
+    def.first_gid = first_gid;
+    def.image = relative(path, &def.image);
+    Ok(def)
+}
+
+/// Check a map can be drawn before loading the rest of it
+fn check_map(orientation: &str, infinite: bool) -> anyhow::Result<()> {
+    if infinite {
+        bail!("infinite maps aren't supported; turn off \"Infinite\" in the map properties");
+    }
+    if !orientation.is_empty() && orientation != "orthogonal" {
+        bail!("only orthogonal maps are supported, not {}", orientation);
+    }
+    Ok(())
+}
+
+/// How many tiles are in each layer of a map `width` by `height` tiles big
+fn layer_len(width: u32, height: u32) -> anyhow::Result<usize> {
+    match width.checked_mul(height) {
+        Some(count) => Ok(count as usize),
+        None => bail!("the map is too big, at {}x{} tiles", width, height),
+    }
+}
+
+/// Decode the csv or base64 tiles of a layer
+fn decode_tiles(
+    data: &str,
+    encoding: &str,
+    compression: &str,
+    len: usize,
+) -> anyhow::Result<Vec<u32>> {
+    let tiles = match encoding {
+        "csv" => data
+            .split(',')
+            .map(|gid| gid.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .context("bad csv tile data")?,
+        "base64" => {
+            let bytes = base64::decode(data.trim()).context("bad base64 tile data")?;
+            let bytes = match compression {
+                "" => bytes,
+                "zlib" => inflate(flate2::read::ZlibDecoder::new(&bytes[..]))?,
+                "gzip" => inflate(flate2::read::GzDecoder::new(&bytes[..]))?,
+                other => bail!(
+                    "{} compression isn't supported; save the map with zlib, gzip or no compression",
+                    other
+                ),
+            };
+            bytes
+                .chunks_exact(4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect()
+        }
+        other => bail!("unknown tile encoding {:?}", other),
+    };
+    check_len(tiles, len)
+}
+
+fn inflate(mut r: impl Read) -> anyhow::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    r.read_to_end(&mut out)
+        .context("bad compressed tile data")?;
+    Ok(out)
+}
+
+fn check_len(tiles: Vec<u32>, len: usize) -> anyhow::Result<Vec<u32>> {
+    if tiles.len() != len {
+        bail!("expected {} tiles in a layer, found {}", len, tiles.len());
+    }
+    Ok(tiles)
+}
+
+/// What a layer inherits from the groups it's in
+#[derive(Clone, Copy)]
+struct Group {
+    visible: bool,
+    opacity: f32,
+    offset: Vector,
+}
+
+impl Group {
+    fn child(self, visible: bool, opacity: f32, offset: Vector) -> Group {
+        Group {
+            visible: self.visible && visible,
+            opacity: self.opacity * opacity,
+            offset: self.offset + offset,
+        }
+    }
+}
+
+const ROOT: Group = Group {
+    visible: true,
+    opacity: 1.0,
+    offset: Vector::ZERO,
+};
+
+// JSON
+
+#[derive(Deserialize)]
+struct JsonMap {
+    width: u32,
+    height: u32,
+    tilewidth: f32,
+    tileheight: f32,
+    #[serde(default)]
+    infinite: bool,
+    #[serde(default)]
+    orientation: String,
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+    #[serde(default)]
+    tilesets: Vec<JsonTileset>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Deserialize)]
+struct JsonProperty {
+    name: String,
+    #[serde(rename = "type", default)]
+    kind: String,
+    value: serde_json::Value,
+}
+
+fn yes() -> bool {
+    true
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize)]
+struct JsonLayer {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default = "yes")]
+    visible: bool,
+    #[serde(default = "one")]
+    opacity: f32,
+    #[serde(default)]
+    offsetx: f32,
+    #[serde(default)]
+    offsety: f32,
+    data: Option<serde_json::Value>,
+    #[serde(default)]
+    encoding: Option<String>,
+    #[serde(default)]
+    compression: Option<String>,
+    #[serde(default)]
+    objects: Vec<JsonObject>,
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Deserialize)]
+struct JsonObject {
+    #[serde(default)]
+    id: u32,
+    #[serde(default)]
+    name: String,
+    #[serde(rename = "type", default)]
+    kind: String,
+    /// What `type` is called since Tiled 1.9
+    #[serde(default)]
+    class: String,
+    x: f32,
+    y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    #[serde(default)]
+    rotation: f32,
+    #[serde(default)]
+    point: bool,
+    #[serde(default)]
+    ellipse: bool,
+    polygon: Option<Vec<JsonPoint>>,
+    polyline: Option<Vec<JsonPoint>>,
+    gid: Option<u32>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Deserialize)]
+struct JsonPoint {
+    x: f32,
+    y: f32,
+}
+
+#[derive(Deserialize)]
+struct JsonTileset {
+    #[serde(default)]
+    firstgid: u32,
+    source: Option<String>,
+    #[serde(default)]
+    name: String,
+    tilewidth: Option<f32>,
+    tileheight: Option<f32>,
+    tilecount: Option<u32>,
+    columns: Option<u32>,
+    #[serde(default)]
+    margin: u32,
+    #[serde(default)]
+    spacing: u32,
+    image: Option<String>,
+    #[serde(default)]
+    tiles: Vec<JsonTile>,
+}
+
+#[derive(Deserialize)]
+struct JsonTile {
+    id: u32,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+fn json_properties(props: Vec<JsonProperty>) -> Properties {
+    props
+        .into_iter()
+        .map(|p| (p.name, Property::from_json(&p.kind, p.value)))
+        .collect()
+}
+
+fn parse_json_map(data: &[u8]) -> anyhow::Result<MapDef<TilesetRef>> {
+    let map: JsonMap = serde_json::from_slice(data)?;
+    check_map(&map.orientation, map.infinite)?;
+    let len = layer_len(map.width, map.height)?;
+    let mut layers = Vec::new();
+    json_layers(&mut layers, map.layers, ROOT, len)?;
+    let tilesets = map
+        .tilesets
+        .into_iter()
+        .map(|t| match t.source {
+            Some(source) => Ok(TilesetRef::External {
+                first_gid: t.firstgid,
+                source,
+            }),
+            None => t.into_def().map(TilesetRef::Def),
+        })
+        .collect::<anyhow::Result<_>>()?;
+    Ok(MapDef {
+        width: map.width,
+        height: map.height,
+        tile_size: Vector::new(map.tilewidth, map.tileheight),
+        tilesets,
+        layers,
+        properties: json_properties(map.properties),
+    })
+}
+
+fn json_layers(
+    out: &mut Vec<Layer>,
+    layers: Vec<JsonLayer>,
+    parent: Group,
+    len: usize,
+) -> anyhow::Result<()> {
+    for layer in layers {
+        let group = parent.child(
+            layer.visible,
+            layer.opacity,
+            Vector::new(layer.offsetx, layer.offsety),
+        );
+        let content = match &*layer.kind {
+            "tilelayer" => {
+                let name = &layer.name;
+                let tiles = match layer.data {
+                    Some(serde_json::Value::String(data)) => decode_tiles(
+                        &data,
+                        layer.encoding.as_deref().unwrap_or("base64"),
+                        layer.compression.as_deref().unwrap_or(""),
+                        len,
+                    ),
+                    Some(data) => serde_json::from_value(data)
+                        .map_err(anyhow::Error::from)
+                        .and_then(|tiles| check_len(tiles, len)),
+                    None => Err(anyhow!("no tile data")),
+                };
+                LayerContent::Tiles(tiles.with_context(|| format!("in layer {:?}", name))?)
+            }
+            "objectgroup" => {
+                LayerContent::Objects(layer.objects.into_iter().map(json_object).collect())
+            }
+            "group" => {
+                json_layers(out, layer.layers, group, len)?;
+                continue;
+            }
+            _ => continue,
+        };
+        out.push(Layer {
+            name: layer.name,
+            visible: group.visible,
+            opacity: group.opacity,
+            offset: group.offset,
+            properties: json_properties(layer.properties),
+            content,
+        });
+    }
+    Ok(())
+}
+
+fn json_object(o: JsonObject) -> Object {
+    let (x, y) = (o.x, o.y);
+    let points = |points: Vec<JsonPoint>| {
+        points
+            .into_iter()
+            .map(|p| Vector::new(x + p.x, y + p.y))
+            .collect()
+    };
+    let (shape, y) = match (o.gid, o.polygon, o.polyline) {
+        (Some(gid), _, _) => (ObjectShape::Tile(gid), y - o.height),
+        (_, Some(p), _) => (ObjectShape::Polygon(points(p)), y),
+        (_, _, Some(p)) => (ObjectShape::Polyline(points(p)), y),
+        _ if o.point => (ObjectShape::Point, y),
+        _ if o.ellipse => (ObjectShape::Ellipse, y),
+        _ => (ObjectShape::Rectangle, y),
+    };
+    Object {
+        id: o.id,
+        name: o.name,
+        kind: if o.kind.is_empty() { o.class } else { o.kind },
+        rect: Rectangle::new(Vector::new(x, y), Vector::new(o.width, o.height)),
+        rotation: o.rotation,
+        shape,
+        properties: json_properties(o.properties),
+    }
+}
+
+impl JsonTileset {
+    fn into_def(self) -> anyhow::Result<TilesetDef> {
+        let image = self.image.ok_or_else(|| {
+            anyhow!("image collection tilesets aren't supported; use a single image instead")
+        })?;
+        let (w, h) = self
+            .tilewidth
+            .zip(self.tileheight)
+            .ok_or_else(|| anyhow!("tileset is missing its tile size"))?;
+        Ok(TilesetDef {
+            name: self.name,
+            first_gid: self.firstgid,
+            tile_count: self.tilecount,
+            columns: self.columns,
+            tile_size: Vector::new(w, h),
+            margin: self.margin,
+            spacing: self.spacing,
+            image,
+            tile_properties: self
+                .tiles
+                .into_iter()
+                .map(|t| (t.id, json_properties(t.properties)))
+                .collect(),
+        })
+    }
+}
+
+// TMX
+
+type Node<'a, 'input> = roxmltree::Node<'a, 'input>;
+
+/// An attribute that has to be there
+fn attr<T: FromStr>(node: Node, name: &str) -> anyhow::Result<T>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    opt_attr(node, name)?
+        .ok_or_else(|| anyhow!("<{}> is missing {:?}", node.tag_name().name(), name))
+}
+
+fn opt_attr<T: FromStr>(node: Node, name: &str) -> anyhow::Result<Option<T>>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    node.attribute(name)
+        .map(|a| {
+            a.parse()
+                .with_context(|| format!("bad {:?} in <{}>", name, node.tag_name().name()))
+        })
+        .transpose()
+}
+
+fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    tag: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children().filter(move |n| n.has_tag_name(tag))
+}
+
+fn tmx_properties(node: Node) -> anyhow::Result<Properties> {
+    let mut props = Properties::new();
+    for list in children(node, "properties") {
+        for p in children(list, "property") {
+            let name: String = attr(p, "name")?;
+            // multi-line strings are saved as text instead of an attribute
+            let value = p.attribute("value").or_else(|| p.text()).unwrap_or("");
+            let kind = p.attribute("type").unwrap_or("string");
+            let value = Property::parse(kind, value)
+                .with_context(|| format!("bad value for property {:?}", name))?;
+            props.insert(name, value);
+        }
+    }
+    Ok(props)
+}
+
+fn parse_tmx_map(text: &str) -> anyhow::Result<MapDef<TilesetRef>> {
+    let doc = roxmltree::Document::parse(text)?;
+    let map = doc.root_element();
+    check_map(
+        map.attribute("orientation").unwrap_or(""),
+        map.attribute("infinite") == Some("1"),
+    )?;
+    let (width, height): (u32, u32) = (attr(map, "width")?, attr(map, "height")?);
+    let mut layers = Vec::new();
+    tmx_layers(&mut layers, map, ROOT, layer_len(width, height)?)?;
+    let tilesets = children(map, "tileset")
+        .map(|t| -> anyhow::Result<_> {
+            let first_gid = attr(t, "firstgid")?;
+            Ok(match t.attribute("source") {
+                Some(source) => TilesetRef::External {
+                    first_gid,
+                    source: source.to_owned(),
+                },
+                None => TilesetRef::Def(TilesetDef {
+                    first_gid,
+                    ..tmx_tileset(t)?
+                }),
+            })
+        })
+        .collect::<anyhow::Result<_>>()?;
+    Ok(MapDef {
+        width,
+        height,
+        tile_size: Vector::new(attr(map, "tilewidth")?, attr(map, "tileheight")?),
+        tilesets,
+        layers,
+        properties: tmx_properties(map)?,
+    })
+}
+
+fn tmx_tileset(t: Node) -> anyhow::Result<TilesetDef> {
+    let image = children(t, "image").next().ok_or_else(|| {
+        anyhow!("image collection tilesets aren't supported; use a single image instead")
+    })?;
+    let mut tile_properties = HashMap::new();
+    for tile in children(t, "tile") {
+        tile_properties.insert(attr(tile, "id")?, tmx_properties(tile)?);
+    }
+    Ok(TilesetDef {
+        name: t.attribute("name").unwrap_or("").to_owned(),
+        first_gid: 0,
+        tile_count: opt_attr(t, "tilecount")?,
+        columns: opt_attr(t, "columns")?,
+        tile_size: Vector::new(attr(t, "tilewidth")?, attr(t, "tileheight")?),
+        margin: opt_attr(t, "margin")?.unwrap_or(0),
+        spacing: opt_attr(t, "spacing")?.unwrap_or(0),
+        image: attr(image, "source")?,
+        tile_properties,
+    })
+}
+
+fn tmx_layers(out: &mut Vec<Layer>, node: Node, parent: Group, len: usize) -> anyhow::Result<()> {
+    for layer in node.children().filter(|n| n.is_element()) {
+        let name = layer.attribute("name").unwrap_or("").to_owned();
+        let group = parent.child(
+            layer.attribute("visible") != Some("0"),
+            opt_attr(layer, "opacity")?.unwrap_or(1.0),
+            Vector::new(
+                opt_attr(layer, "offsetx")?.unwrap_or(0.0),
+                opt_attr(layer, "offsety")?.unwrap_or(0.0),
+            ),
+        );
+        let content = match layer.tag_name().name() {
+            "layer" => LayerContent::Tiles(
+                tmx_tiles(layer, len).with_context(|| format!("in layer {:?}", name))?,
+            ),
+            "objectgroup" => LayerContent::Objects(
+                children(layer, "object")
+                    .map(tmx_object)
+                    .collect::<anyhow::Result<_>>()
+                    .with_context(|| format!("in layer {:?}", name))?,
+            ),
+            "group" => {
+                tmx_layers(out, layer, group, len)?;
+                continue;
+            }
+            _ => continue,
+        };
+        out.push(Layer {
+            name,
+            visible: group.visible,
+            opacity: group.opacity,
+            offset: group.offset,
+            properties: tmx_properties(layer)?,
+            content,
+        });
+    }
+    Ok(())
+}
+
+fn tmx_tiles(layer: Node, len: usize) -> anyhow::Result<Vec<u32>> {
+    let data = children(layer, "data")
+        .next()
+        .ok_or_else(|| anyhow!("no tile data"))?;
+    match data.attribute("encoding") {
+        Some(encoding) => decode_tiles(
+            data.text().unwrap_or(""),
+            encoding,
+            data.attribute("compression").unwrap_or(""),
+            len,
+        ),
+        None => {
+            let tiles = children(data, "tile")
+                .map(|t| -> anyhow::Result<u32> { Ok(opt_attr(t, "gid")?.unwrap_or(0)) })
+                .collect::<anyhow::Result<_>>()?;
+            check_len(tiles, len)
+        }
+    }
+}
+
+fn tmx_object(o: Node) -> anyhow::Result<Object> {
+    let (x, y): (f32, f32) = (attr(o, "x")?, attr(o, "y")?);
+    let (w, h): (f32, f32) = (
+        opt_attr(o, "width")?.unwrap_or(0.0),
+        opt_attr(o, "height")?.unwrap_or(0.0),
+    );
+    let points = |n: Node| -> anyhow::Result<Vec<Vector>> {
+        n.attribute("points")
+            .unwrap_or("")
+            .split_whitespace()
+            .map(|p| {
+                let mut xy = p.split(',').map(f32::from_str);
+                match (xy.next(), xy.next()) {
+                    (Some(Ok(px)), Some(Ok(py))) => Ok(Vector::new(x + px, y + py)),
+                    _ => Err(anyhow!("bad point {:?}", p)),
+                }
+            })
+            .collect()
+    };
+    let mut shape = match opt_attr::<u32>(o, "gid")? {
+        Some(gid) => ObjectShape::Tile(gid),
+        None => ObjectShape::Rectangle,
+    };
+    for child in o.children() {
+        shape = match child.tag_name().name() {
+            "ellipse" => ObjectShape::Ellipse,
+            "point" => ObjectShape::Point,
+            "polygon" => ObjectShape::Polygon(points(child)?),
+            "polyline" => ObjectShape::Polyline(points(child)?),
+            _ => continue,
+        };
+    }
+    let y = match shape {
+        ObjectShape::Tile(_) => y - h,
+        _ => y,
+    };
+    Ok(Object {
+        id: opt_attr(o, "id")?.unwrap_or(0),
+        name: o.attribute("name").unwrap_or("").to_owned(),
+        kind: o
+            .attribute("type")
+            .or_else(|| o.attribute("class"))
+            .unwrap_or("")
+            .to_owned(),
+        rect: Rectangle::new(Vector::new(x, y), Vector::new(w, h)),
+        rotation: opt_attr(o, "rotation")?.unwrap_or(0.0),
+        shape,
+        properties: tmx_properties(o)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const TILES: [u32; 6] = [1, 2, 0, 3 | FLIP_X, 0, 4];
+
+    fn bytes(tiles: &[u32]) -> Vec<u8> {
+        tiles
+            .iter()
+            .flat_map(|t| t.to_le_bytes().to_vec())
+            .collect()
+    }
+
+    fn zlib(tiles: &[u32]) -> String {
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(&bytes(tiles)).unwrap();
+        base64::encode(encoder.finish().unwrap())
+    }
+
+    fn gzip(tiles: &[u32]) -> String {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(&bytes(tiles)).unwrap();
+        base64::encode(encoder.finish().unwrap())
+    }
+
+    fn tiles(layer: &Layer) -> &[u32] {
+        match &layer.content {
+            LayerContent::Tiles(tiles) => tiles,
+            LayerContent::Objects(_) => panic!("{:?} is an object layer", layer.name),
+        }
+    }
+
+    fn layer(offset: Vector) -> Layer {
+        Layer {
+            name: String::new(),
+            visible: true,
+            opacity: 1.0,
+            offset,
+            properties: Properties::new(),
+            content: LayerContent::Tiles(Vec::new()),
+        }
+    }
+
+    #[test]
+    fn decodes_every_encoding() {
+        let csv = "1,2,0,\n2147483651, 0,4\n";
+        assert_eq!(decode_tiles(csv, "csv", "", 6).unwrap(), TILES);
+        let plain = base64::encode(bytes(&TILES));
+        assert_eq!(decode_tiles(&plain, "base64", "", 6).unwrap(), TILES);
+        let zlib = format!("\n   {}\n  ", zlib(&TILES));
+        assert_eq!(decode_tiles(&zlib, "base64", "zlib", 6).unwrap(), TILES);
+        assert_eq!(
+            decode_tiles(&gzip(&TILES), "base64", "gzip", 6).unwrap(),
+            TILES
+        );
+    }
+
+    #[test]
+    fn rejects_bad_tile_data() {
+        assert!(decode_tiles("1,2,3", "csv", "", 6).is_err());
+        assert!(decode_tiles("1,x", "csv", "", 2).is_err());
+        assert!(decode_tiles("not base64!", "base64", "", 6).is_err());
+        assert!(decode_tiles(&zlib(&TILES), "base64", "zstd", 6).is_err());
+        assert!(decode_tiles(&zlib(&TILES), "base64", "gzip", 6).is_err());
+        assert!(decode_tiles(&zlib(&TILES), "base64", "zlib", 5).is_err());
+        assert!(decode_tiles("", "xml", "", 0).is_err());
+    }
+
+    #[test]
+    fn layer_len_overflow() {
+        assert_eq!(layer_len(3, 2).unwrap(), 6);
+        assert_eq!(layer_len(u32::MAX, 1).unwrap(), u32::MAX as usize);
+        assert!(layer_len(65536, 65536).is_err());
+        let map = r#"{ "width": 4294967295, "height": 2, "tilewidth": 16, "tileheight": 16 }"#;
+        assert!(parse_json_map(map.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn parses_tmx() {
+        let tmx = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map orientation="orthogonal" width="3" height="2" tilewidth="16" tileheight="8" infinite="0">
+  <properties><property name="gravity" type="float" value="9.5"/></properties>
+  <tileset firstgid="1" name="terrain" tilewidth="16" tileheight="8" tilecount="4" columns="2">
+    <image source="terrain.png" width="32" height="16"/>
+    <tile id="1"><properties><property name="solid" type="bool" value="true"/></properties></tile>
+  </tileset>
+  <tileset firstgid="5" source="items.tsx"/>
+  <layer name="ground" width="3" height="2">
+    <data encoding="base64" compression="zlib">{}</data>
+  </layer>
+  <group name="extra" visible="0" opacity="0.5" offsetx="4">
+    <layer name="hidden" opacity="0.5" offsety="2">
+      <data><tile gid="1"/><tile/><tile/><tile/><tile/><tile gid="2"/></data>
+    </layer>
+  </group>
+  <objectgroup name="spawns">
+    <object id="3" name="player" type="start" x="8" y="4" width="16" height="8"/>
+    <object id="4" x="1" y="2"><point/></object>
+  </objectgroup>
+</map>"#,
+            zlib(&TILES)
+        );
+        let map = parse_tmx_map(&tmx).unwrap();
+        assert_eq!((map.width, map.height), (3, 2));
+        assert_eq!(map.tile_size, Vector::new(16.0, 8.0));
+        assert!(matches!(map.properties["gravity"], Property::Float(g) if g == 9.5));
+
+        match &map.tilesets[..] {
+            [TilesetRef::Def(def), TilesetRef::External { first_gid, source }] => {
+                assert_eq!(
+                    (def.first_gid, def.tile_count, def.columns),
+                    (1, Some(4), Some(2))
+                );
+                assert_eq!(def.image, "terrain.png");
+                assert!(matches!(
+                    def.tile_properties[&1]["solid"],
+                    Property::Bool(true)
+                ));
+                assert_eq!((*first_gid, source.as_str()), (5, "items.tsx"));
+            }
+            _ => panic!("expected an embedded and an external tileset"),
+        }
+
+        let names: Vec<&str> = map.layers.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["ground", "hidden", "spawns"]);
+        assert_eq!(tiles(&map.layers[0]), TILES);
+        let hidden = &map.layers[1];
+        assert_eq!(tiles(hidden), [1, 0, 0, 0, 0, 2]);
+        assert!(!hidden.visible);
+        assert_eq!(hidden.opacity, 0.25);
+        assert_eq!(hidden.offset, Vector::new(4.0, 2.0));
+
+        match &map.layers[2].content {
+            LayerContent::Objects(objects) => {
+                assert_eq!(objects.len(), 2);
+                assert_eq!(
+                    (objects[0].id, &*objects[0].name, &*objects[0].kind),
+                    (3, "player", "start")
+                );
+                assert_eq!(
+                    objects[0].rect,
+                    Rectangle::new(Vector::new(8.0, 4.0), Vector::new(16.0, 8.0))
+                );
+                assert!(matches!(objects[1].shape, ObjectShape::Point));
+            }
+            LayerContent::Tiles(_) => panic!("expected an object layer"),
+        }
+    }
+
+    #[test]
+    fn parses_json() {
+        let json = format!(
+            r#"{{
+                "width": 3, "height": 2, "tilewidth": 16, "tileheight": 8,
+                "orientation": "orthogonal", "infinite": false,
+                "properties": [{{ "name": "level", "type": "int", "value": 2 }}],
+                "tilesets": [
+                    {{ "firstgid": 1, "name": "terrain", "tilewidth": 16, "tileheight": 8,
+                       "image": "terrain.png", "tiles": [{{ "id": 1, "properties":
+                       [{{ "name": "solid", "type": "bool", "value": true }}] }}] }},
+                    {{ "firstgid": 5, "source": "items.tsj" }}
+                ],
+                "layers": [
+                    {{ "type": "tilelayer", "name": "ground", "data": "{}",
+                       "encoding": "base64", "compression": "zlib" }},
+                    {{ "type": "group", "name": "extra", "visible": false, "offsetx": 4,
+                       "layers": [{{ "type": "tilelayer", "name": "csv", "opacity": 0.5,
+                                     "data": [1, 2, 0, 2147483651, 0, 4] }}] }},
+                    {{ "type": "imagelayer", "name": "sky" }},
+                    {{ "type": "objectgroup", "name": "spawns", "objects": [
+                        {{ "id": 3, "name": "player", "class": "start", "x": 8, "y": 4,
+                           "width": 16, "height": 8 }}
+                    ] }}
+                ]
+            }}"#,
+            zlib(&TILES)
+        );
+        let map = parse_json_map(json.as_bytes()).unwrap();
+        assert_eq!((map.width, map.height), (3, 2));
+        assert!(matches!(map.properties["level"], Property::Int(2)));
+
+        match &map.tilesets[..] {
+            [TilesetRef::Def(def), TilesetRef::External { first_gid, source }] => {
+                assert_eq!(
+                    (def.first_gid, def.tile_count, def.columns),
+                    (1, None, None)
+                );
+                assert!(matches!(
+                    def.tile_properties[&1]["solid"],
+                    Property::Bool(true)
+                ));
+                assert_eq!((*first_gid, source.as_str()), (5, "items.tsj"));
+            }
+            _ => panic!("expected an embedded and an external tileset"),
+        }
+
+        let names: Vec<&str> = map.layers.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["ground", "csv", "spawns"]);
+        assert_eq!(tiles(&map.layers[0]), TILES);
+        assert_eq!(tiles(&map.layers[1]), TILES);
+        assert!(map.layers[0].visible);
+        assert!(!map.layers[1].visible);
+        assert_eq!(map.layers[1].offset, Vector::new(4.0, 0.0));
+        match &map.layers[2].content {
+            LayerContent::Objects(objects) => assert_eq!(objects[0].kind, "start"),
+            LayerContent::Tiles(_) => panic!("expected an object layer"),
+        }
+    }
+
+    #[test]
+    fn rejects_maps_that_cant_be_drawn() {
+        let map = |extra: &str| {
+            let json = format!(
+                r#"{{ "width": 1, "height": 1, "tilewidth": 16, "tileheight": 16 {} }}"#,
+                extra
+            );
+            parse_json_map(json.as_bytes())
+        };
+        assert!(map("").is_ok());
+        assert!(map(r#", "infinite": true"#).is_err());
+        assert!(map(r#", "orientation": "isometric""#).is_err());
+        let short = r#", "layers": [{ "type": "tilelayer", "data": [1, 2] }]"#;
+        assert!(map(short).is_err());
+    }
+
+    #[test]
+    fn tile_pos() {
+        let map = Tilemap {
+            width: 3,
+            height: 2,
+            tile_size: Vector::new(16.0, 8.0),
+            tilesets: Vec::new(),
+            layers: Vec::new(),
+            properties: Properties::new(),
+        };
+        let at = |layer: &Layer, x: f32, y: f32| map.tile_pos(layer, Vector::new(x, y));
+        let plain = layer(Vector::ZERO);
+        assert_eq!(at(&plain, 0.0, 0.0), Some((0, 0)));
+        assert_eq!(at(&plain, 15.9, 7.9), Some((0, 0)));
+        assert_eq!(at(&plain, 16.0, 8.0), Some((1, 1)));
+        assert_eq!(at(&plain, 47.9, 15.9), Some((2, 1)));
+        assert_eq!(at(&plain, 48.0, 0.0), None);
+        assert_eq!(at(&plain, 0.0, 16.0), None);
+        assert_eq!(at(&plain, -0.1, 0.0), None);
+        assert_eq!(at(&plain, f32::NAN, 0.0), None);
+
+        let moved = layer(Vector::new(-16.0, 4.0));
+        assert_eq!(at(&moved, 0.0, 4.0), Some((1, 0)));
+        assert_eq!(at(&moved, 0.0, 3.9), None);
+        assert_eq!(strip_flags(3 | FLIP_X | FLIP_DIAGONAL), 3);
+    }
+}
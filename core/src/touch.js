@@ -0,0 +1,38 @@
+const queue = [];
+let emulating = false;
+
+const PHASES = ["touchstart", "touchmove", "touchend", "touchcancel"];
+
+export function install() {
+    const canvas = document.querySelector("canvas");
+    PHASES.forEach((name, phase) => {
+        canvas.addEventListener(
+            name,
+            (e) => {
+                // while we're emulating the mouse ourselves, stop the browser from scrolling or
+                // synthesizing mouse events too; otherwise taps should still be clicks
+                if (emulating) {
+                    e.preventDefault();
+                }
+                const rect = canvas.getBoundingClientRect();
+                for (const touch of e.changedTouches) {
+                    queue.push(
+                        touch.identifier,
+                        phase,
+                        touch.clientX - rect.left,
+                        touch.clientY - rect.top
+                    );
+                }
+            },
+            { passive: false }
+        );
+    });
+}
+
+export function set_emulating(enabled) {
+    emulating = enabled;
+}
+
+export function drain() {
+    return new Float64Array(queue.splice(0, queue.length));
+}
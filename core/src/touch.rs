@@ -0,0 +1,96 @@
+//! touch input, which quicksilver doesn't report itself
+
+use crate::prelude::*;
+
+// touches only come from the web
+#[cfg_attr(not(target_arch = "wasm32"), allow(unused))]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TouchPhase {
+    Start,
+    Move,
+    End,
+    Cancel,
+}
+
+impl TouchPhase {
+    pub fn name(self) -> &'static str {
+        match self {
+            TouchPhase::Start => "start",
+            TouchPhase::Move => "move",
+            TouchPhase::End => "end",
+            TouchPhase::Cancel => "cancel",
+        }
+    }
+
+    /// Whether the touch point is gone after this phase
+    pub fn is_finished(self) -> bool {
+        matches!(self, TouchPhase::End | TouchPhase::Cancel)
+    }
+}
+
+pub struct TouchEvent {
+    pub id: i64,
+    pub phase: TouchPhase,
+    /// in window coordinates
+    pub location: Vector,
+}
+
+#[cfg(target_arch = "wasm32")]
+mod platform {
+    use super::*;
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen(module = "/src/touch.js")]
+    extern "C" {
+        fn install();
+        fn set_emulating(enabled: bool);
+        fn drain() -> js_sys::Float64Array;
+    }
+
+    static INSTALL: std::sync::Once = std::sync::Once::new();
+
+    pub fn poll(emulate_mouse: bool) -> Vec<TouchEvent> {
+        INSTALL.call_once(install);
+        set_emulating(emulate_mouse);
+        drain()
+            .to_vec()
+            .chunks_exact(4)
+            .map(|t| TouchEvent {
+                id: t[0] as i64,
+                phase: match t[1] as u8 {
+                    0 => TouchPhase::Start,
+                    1 => TouchPhase::Move,
+                    2 => TouchPhase::End,
+                    _ => TouchPhase::Cancel,
+                },
+                location: Vector::new(t[2] as f32, t[3] as f32),
+            })
+            .collect()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod platform {
+    use super::*;
+
+    // quicksilver doesn't pass touch events through on desktop
+    pub fn poll(_emulate_mouse: bool) -> Vec<TouchEvent> {
+        Vec::new()
+    }
+}
+
+/// Take the touch events that happened since the last call
+///
+/// The browser only stops turning taps into mouse clicks itself while `emulate_mouse` is on,
+/// so games that don't know about touches still get clicks.
+pub fn poll(emulate_mouse: bool) -> Vec<TouchEvent> {
+    platform::poll(emulate_mouse)
+}
+
+/// A touch point that is currently on the screen
+pub struct Touch {
+    pub id: i64,
+    /// in camera coordinates, like `State::mouse_pos`
+    pub pos: Vector,
+    pub phase: TouchPhase,
+}
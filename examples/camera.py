@@ -6,14 +6,13 @@ def init():
     return [[0, 0], [800, 600]]
 
 def update(state):
-    keys = qs.keyboard_bool()
-    if keys["Left"] or keys["A"]:
+    if qs.key_down("Left") or qs.key_down("A"):
         state[0][0] -= 4
-    if keys["Right"] or keys["D"]:
+    if qs.key_down("Right") or qs.key_down("D"):
         state[0][0] += 4
-    if keys["Up"] or keys["W"]:
+    if qs.key_down("Up") or qs.key_down("W"):
         state[0][1] -= 4
-    if keys["Down"] or keys["S"]:
+    if qs.key_down("Down") or qs.key_down("S"):
         state[0][1] += 4
 
 def draw(state):
@@ -24,6 +23,8 @@ def draw(state):
     qs.rect([[100,100], [32,32]], color=BLUE)
     # Draw a blue rectangle, rotated by 45 degrees, with a z-height of 10
     qs.rect([[400, 300], [32, 32]], color=BLUE, transform=rotate(45))
+    # Draw a black outline around a rectangle without filling it, with rounded corners
+    qs.rect([[200, 400], [64, 64]], fill=False, outline=BLACK, thickness=4., join="round")
     # Draw a green circle with its center at (400, 300) and a radius of 100
     qs.circ( [400, 300], 100., color=GREEN)
     # Draw a red line with thickness of 2 pixels and z-height of 5
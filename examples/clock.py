@@ -4,7 +4,6 @@ from common import *
 def init():
     qs.init_sounds([ ["click", "click.wav"] ])
     return {
-        "elapsed": 0,
         "hours": 0,
         "minutes": 0,
         "seconds": 0,
@@ -14,11 +13,10 @@ def onload(_):
     qs.set_update_rate(1000)
 
 def update(state):
-    state["elapsed"] += qs.update_rate()
-    elapsed = state["elapsed"]
-    state["seconds"] = (elapsed / 1000.) % 60.
-    state["minutes"] = ((elapsed / 1000.) / 60.) % 60.
-    state["hours"] = ((elapsed / 1000.) / 60. / 24.) % 24.
+    elapsed = qs.time()
+    state["seconds"] = elapsed % 60.
+    state["minutes"] = (elapsed / 60.) % 60.
+    state["hours"] = (elapsed / 3600.) % 24.
 
     qs.sound("click")
 
@@ -11,6 +11,12 @@ def update(_state):
 def draw(_state):
     # Remove any artifacts from the previous frame
     qs.clear(WHITE)
+    # Draw a sky that fades from blue at the top to white at the bottom
+    sky = {"linear": [[0, 0], [0, 600]], "stops": [[0, BLUE], [1, WHITE]]}
+    qs.rect([[0, 0], [800, 600]], color=sky)
+    # Draw a glow that fades out from its center
+    glow = {"radial": [[400, 300], 150], "stops": [[0, [1., 1., 0., 1.]], [1, [1., 1., 0., 0.]]]}
+    qs.circ([400, 300], 150., color=glow)
     # Draw a blue rectangle with a top-left corner at (100, 100) and a width and height of 32
     qs.rect([[100,100], [32,32]], color=BLUE)
     # Draw a green circle with its center at (400, 300) and a radius of 100
@@ -19,8 +25,27 @@ def draw(_state):
     qs.line([[50, 80], [600, 450]], thickness=2., color=RED)
     # Draw a red triangle rotated by 45 degrees, and scaled down to half
     qs.triangle([[500, 50], [450, 100], [650, 150]], color=RED, transform=matmul(rotate(45), scale(0.5, 0.5)))
+    # Draw a triangle with a different color at each corner
+    qs.triangle([[100, 200], [200, 200], [150, 120]], colors=[RED, GREEN, BLUE])
     # Draw a blue rectangle, rotated by 45 degrees, with a z-height of 10
     qs.rect([[400, 300], [32, 32]], color=BLUE, transform=rotate(45))
+    # Draw a concave arrow-shaped polygon with a black outline
+    qs.polygon([[650, 400], [750, 450], [650, 500], [680, 450]], color=GREEN, outline=BLACK, thickness=2.)
+    # Draw a zig-zag line with rounded joins
+    qs.polyline([[50, 550], [100, 500], [150, 550], [200, 500]], thickness=6., join="round", color=BLUE)
+    # Draw a three-quarters full cooldown timer
+    qs.pie([700, 100], 40., -90., 180., color=BLUE)
+    # Draw a panel with rounded corners
+    qs.rounded_rect([[250, 480], [200, 80]], 16., color=GREEN, outline=BLACK, thickness=2.)
+    # Draw a sagging rope between two points
+    qs.bezier([[500, 250], [575, 350], [650, 250]], thickness=3., color=BLACK)
+    # Build a leaf shape out of curves, then fill and outline it
+    qs.begin_path()
+    qs.move_to([50, 300])
+    qs.quad_to([100, 220], [150, 300])
+    qs.quad_to([100, 380], [50, 300])
+    qs.close_path()
+    qs.fill_path(color=GREEN, outline=BLACK, thickness=2.)
 
 def event(state, event):
     pass
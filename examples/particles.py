@@ -0,0 +1,30 @@
+""" Draw thousands of sprites and rectangles with one call each. """
+import math
+import qs
+from common import *
+
+def init():
+    qs.init_sprites([
+        ["crab", "crab.png"],
+    ])
+    return {"t": 0.}
+
+def update(state):
+    state["t"] += 1.
+
+def draw(state):
+    qs.clear(WHITE)
+    t = state["t"]
+    # Each crab is [x, y, rotation, scale]
+    crabs = [
+        [400. + math.cos(i + t / 60.) * i / 4., 300. + math.sin(i + t / 60.) * i / 4., i + t, 0.5]
+        for i in range(1000)
+    ]
+    qs.sprites("crab", crabs)
+    # Each rectangle is [p0, size], with an optional color of its own
+    confetti = [[[(i * 37) % 800, (i * 53 + t) % 600], [4, 4]] for i in range(2000)]
+    confetti.append([[0, 0], [800, 8], BLUE])
+    qs.rects(confetti, color=GREEN)
+
+def event(state, event):
+    pass
@@ -11,5 +11,5 @@ def draw(_):
 def event(_, evt):
     if evt.event == "mouse_button" \
         and evt.button == "Left" \
-        and evt.state == "Pressed":
+        and evt.state == qs.PRESSED:
         qs.sound("click")
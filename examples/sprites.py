@@ -9,6 +9,8 @@ def init():
         ["crab-left", "crab-left.png", 2, 1.],
         ["crab-up", "crab-up.png", 2, 1.]
     ])
+    # Each frame of crab-left.png can be drawn on its own, as "crab-frames/left-1" and so on
+    qs.init_atlas("crab-frames", "crab-left.png", "crab-left.json")
     qs.init_sounds([
         ["click", "click.wav"]
     ])
@@ -30,6 +32,11 @@ def draw(state):
     transform = rotate(state["deg"])
     # qs.clear(BLACK)
     qs.anim("crab-left", rect=[p0, p1])
+    qs.sprite("crab-frames/left-2", p0=state["p2"])
+    # Mirror the crab instead of loading a second image, and spin it around its feet
+    qs.sprite("crab", p0=[400., 300.], flip_x=True, origin=[0.5, 1.], transform=transform)
+    # Draw just the left half of the crab, tinted red and half transparent
+    qs.sprite("crab", p0=[500., 300.], src=[[0, 0], [18, 27]], tint=RED, alpha=0.5)
 
 def event(state, event):
     if event.event == "mouse_moved":
@@ -0,0 +1,29 @@
+""" Draw a minimap to a surface, then draw the surface as a sprite. """
+import qs
+from common import *
+
+def init():
+    return 0
+
+def onload(state):
+    # A surface is an image that can be drawn to, which needs the game to have loaded
+    qs.create_surface("minimap", 200, 150)
+
+def update(state):
+    pass
+
+def draw(state):
+    # Everything drawn after draw_to goes to the surface instead of the screen
+    qs.draw_to("minimap")
+    qs.clear(BLACK)
+    qs.circ([100, 75], 50., color=GREEN)
+    qs.rect([[90, 65], [20, 20]], color=RED)
+    # Go back to drawing to the screen
+    qs.draw_to_screen()
+    qs.clear(WHITE)
+    qs.rect([[100, 100], [32, 32]], color=BLUE)
+    # Surfaces are drawn just like sprites
+    qs.sprite("minimap", p0=[580, 20])
+
+def event(state, event):
+    pass
@@ -0,0 +1,49 @@
+""" Load a map made with Tiled, walk around it and bump into its walls. """
+import qs
+from common import *
+
+SIZE = 16
+
+def init():
+    qs.init_sprites([
+        ["crab", "crab.png"],
+    ])
+    qs.init_tilemaps([
+        ["level", "level.tmx"],
+    ])
+    return {"player": None, "goal": None}
+
+def solid(x, y):
+    tile = qs.tile_at("level", "ground", x, y)
+    return tile is not None and tile.properties.get("solid", False)
+
+def update(state):
+    if state["player"] is None:
+        # Objects are placed in Tiled, in an object layer
+        for obj in qs.tilemap_objects("level", "spawns"):
+            if obj.name == "player":
+                state["player"] = [obj.x, obj.y]
+            elif obj.name == "goal":
+                state["goal"] = obj.rect
+    x, y = state["player"]
+    dx = (qs.key_down("Right") - qs.key_down("Left")) * 2
+    dy = (qs.key_down("Down") - qs.key_down("Up")) * 2
+    corners = [(0, 0), (SIZE - 1, 0), (0, SIZE - 1), (SIZE - 1, SIZE - 1)]
+    if not any(solid(x + dx + cx, y + cy) for cx, cy in corners):
+        x += dx
+    if not any(solid(x + cx, y + dy + cy) for cx, cy in corners):
+        y += dy
+    state["player"] = [x, y]
+
+def draw(state):
+    qs.clear(BLACK)
+    x, y = state["player"] or [0, 0]
+    # A window-sized view centred on the player; only the tiles that can be seen are drawn
+    qs.set_view([[x - 400, y - 300], [800, 600]])
+    qs.draw_tilemap("level", "ground")
+    if state["goal"] is not None:
+        qs.rect(state["goal"], fill=False, outline=GREEN, thickness=2.)
+    qs.sprite("crab", rect=[[x, y], [SIZE, SIZE]])
+
+def event(state, event):
+    pass
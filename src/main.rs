@@ -46,6 +46,9 @@ enum Pyckitup {
         filename: PathBuf,
         #[structopt(flatten)]
         size: SizeArg,
+        /// What to do when the game raises an exception: crash, screen or skip.
+        #[structopt(long, value_name = "MODE", default_value = "screen")]
+        on_error: pyckitup_core::ErrorMode,
     },
     /// Initialize a new pyckitup project
     Init {
@@ -68,7 +71,11 @@ fn main() -> anyhow::Result<()> {
     let opts = Pyckitup::from_args();
     match opts {
         #[cfg(feature = "run-desktop")]
-        Pyckitup::Run { filename, size } => {
+        Pyckitup::Run {
+            filename,
+            size,
+            on_error,
+        } => {
             if !filename.exists() {
                 println!("File `./run.py` doesn't exist. Doing nothing.");
                 std::process::exit(1);
@@ -80,6 +87,9 @@ fn main() -> anyhow::Result<()> {
                 width,
                 height,
                 filename: Some(filename),
+                debug_hotkey: true,
+                on_error,
+                on_exit: Some(|code| std::process::exit(code)),
                 ..Default::default()
             });
         }